@@ -0,0 +1 @@
+ALTER TABLE dependencies DROP COLUMN req_is_raw;
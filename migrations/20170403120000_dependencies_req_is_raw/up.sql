@@ -0,0 +1 @@
+ALTER TABLE dependencies ADD COLUMN req_is_raw BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1 @@
+ALTER TABLE crates DROP COLUMN reverse_dependency_downloads;
@@ -10,6 +10,7 @@ use r2d2;
 use curl::easy::Easy;
 
 use {db, Config};
+use dependency::background;
 
 /// The `App` struct holds the main components of the application like
 /// the database connection pool and configurations
@@ -20,6 +21,9 @@ pub struct App {
     /// The database connection pool
     pub diesel_database: db::DieselPool,
 
+    /// The threads background dependency queries run on
+    pub dependency_workers: background::Workers,
+
     /// The GitHub OAuth2 configuration
     pub github: oauth2::Config,
 
@@ -61,6 +65,7 @@ impl App {
         App {
             database: db::pool(&config.db_url, db_config),
             diesel_database: db::diesel_pool(&config.db_url, diesel_db_config),
+            dependency_workers: background::Workers::new(config.dependency_workers),
             github: github,
             session_key: config.session_key.clone(),
             git_repo: Mutex::new(repo),
@@ -33,6 +33,8 @@ fn main() {
         max_upload_size: 0,
         mirror: Replica::Primary,
         api_protocol: api_protocol,
+        dependency_policy: Default::default(),
+        dependency_workers: 1,
     };
     let app = cargo_registry::App::new(&config);
     {
@@ -106,6 +106,10 @@ fn main() {
         max_upload_size: 10 * 1024 * 1024,
         mirror: mirror,
         api_protocol: api_protocol,
+        dependency_policy: Default::default(),
+        dependency_workers: env::var("DEPENDENCY_WORKERS").ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(if cargo_env == Env::Production {4} else {1}),
     };
     let app = cargo_registry::App::new(&config);
     let app = cargo_registry::middleware(Arc::new(app));
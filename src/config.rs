@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 use {Uploader, Replica};
+use dependency::DependencyPolicy;
 
 #[derive(Clone)]
 pub struct Config {
@@ -13,4 +14,7 @@ pub struct Config {
     pub max_upload_size: u64,
     pub mirror: Replica,
     pub api_protocol: String,
+    pub dependency_policy: DependencyPolicy,
+    /// How many threads background dependency queries run on
+    pub dependency_workers: usize,
 }
@@ -0,0 +1,54 @@
+//! A trail of the changes `add_dependencies` makes to, or the objections it
+//! raises against, the dependencies of a crate being published.
+
+/// What happened to a dependency
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AuditAction {
+    /// The publish was refused because of this dependency
+    Rejected,
+    /// The dependency was accepted after being rewritten
+    Normalized,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditEvent {
+    /// The name of the dependency, as written by the publisher. This is
+    /// empty for events about no dependency in particular.
+    pub dependency: String,
+    /// The offending value, such as the version requirement or target
+    pub original: String,
+    pub action: AuditAction,
+    pub reason: String,
+}
+
+impl AuditEvent {
+    /// An event about the dependencies of a version as a whole, or about
+    /// something which names a dependency the version doesn't have
+    pub fn without_dependency(original: &str, action: AuditAction, reason: &str)
+                              -> AuditEvent {
+        AuditEvent {
+            dependency: String::new(),
+            original: original.to_string(),
+            action: action,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// Something unusual about a publish as a whole, which operators may want to
+/// keep an eye on but which doesn't stop the publish from going through
+#[derive(Clone, Debug, PartialEq)]
+pub enum Signal {
+    /// A version declared more dependencies than the policy's
+    /// `dependency_baseline`
+    ManyDependencies { version_id: i32, count: usize, baseline: usize },
+}
+
+/// Receives an `AuditEvent` for every dependency that gets rejected or
+/// normalized. Events are recorded as they happen, so they are seen even if
+/// the publish fails later on and its transaction is rolled back.
+pub trait AuditHook: Send + Sync {
+    fn record(&self, event: &AuditEvent);
+
+    fn signal(&self, _signal: &Signal) {}
+}
@@ -0,0 +1,135 @@
+//! Runs dependency queries off the calling thread, so callers which can't
+//! afford to block (such as a future async handler) can start a query and
+//! collect its result later. There's no async runtime in the tree yet, so
+//! queries are queued onto a fixed set of worker threads owned by the `App`,
+//! each query taking a connection from the app's pools when it runs.
+//!
+//! The synchronous functions in `dependency` remain the primary interface,
+//! these are only thin wrappers around them.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use diesel::pg::PgConnection;
+use pg::GenericConnection;
+
+use app::App;
+use krate::Crate;
+use util::{CargoResult, internal};
+use Model;
+use super::{Dependency, DependencySurface, ReverseDependency};
+
+trait Job: Send {
+    fn run(self: Box<Self>);
+}
+
+impl<F: FnOnce() + Send> Job for F {
+    fn run(self: Box<Self>) {
+        (*self)()
+    }
+}
+
+/// A fixed number of threads which background queries are queued onto
+pub struct Workers {
+    jobs: Mutex<Sender<Box<Job>>>,
+}
+
+impl Workers {
+    pub fn new(threads: usize) -> Workers {
+        let (tx, rx) = mpsc::channel::<Box<Job>>();
+        let rx = Arc::new(Mutex::new(rx));
+        for _ in 0..threads {
+            let rx = rx.clone();
+            thread::spawn(move || loop {
+                // The lock is only held while waiting for the next job
+                let job = match rx.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+                // A panicking query is reported through its `Pending`, it
+                // shouldn't take the worker down with it
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| job.run()));
+            });
+        }
+        Workers { jobs: Mutex::new(tx) }
+    }
+
+    fn run<T, F>(&self, query: F) -> Pending<T>
+        where T: Send + 'static,
+              F: FnOnce() -> CargoResult<T> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let job = move || {
+            let _ = tx.send(query());
+        };
+        // If the workers are gone the job is dropped along with its sender,
+        // which `wait` reports as an error
+        let _ = self.jobs.lock().unwrap().send(Box::new(job));
+        Pending { result: rx }
+    }
+}
+
+/// A query running in the background
+pub struct Pending<T> {
+    result: Receiver<CargoResult<T>>,
+}
+
+impl<T> Pending<T> {
+    /// Blocks until the query completes, returning its result
+    pub fn wait(self) -> CargoResult<T> {
+        self.result.recv().unwrap_or_else(|_| {
+            Err(internal("background dependency query panicked"))
+        })
+    }
+}
+
+fn diesel<T, F>(app: &App, query: F) -> Pending<T>
+    where T: Send + 'static,
+          F: FnOnce(&PgConnection) -> CargoResult<T> + Send + 'static,
+{
+    let pool = app.diesel_database.clone();
+    app.dependency_workers.run(move || {
+        let conn = pool.get().map_err(|e| {
+            internal(&format_args!("failed to get a database connection: {}", e))
+        })?;
+        query(&conn)
+    })
+}
+
+fn postgres<T, F>(app: &App, query: F) -> Pending<T>
+    where T: Send + 'static,
+          F: FnOnce(&GenericConnection) -> CargoResult<T> + Send + 'static,
+{
+    let pool = app.database.clone();
+    app.dependency_workers.run(move || {
+        let conn = pool.get().map_err(|e| {
+            internal(&format_args!("failed to get a database connection: {}", e))
+        })?;
+        query(&*conn)
+    })
+}
+
+pub fn find_by_version_id(app: &App, version_id: i32) -> Pending<Vec<Dependency>> {
+    diesel(app, move |conn| super::find_by_version_id(conn, version_id))
+}
+
+pub fn dependency_surface(app: &App, version_id: i32) -> Pending<DependencySurface> {
+    diesel(app, move |conn| super::dependency_surface(conn, version_id))
+}
+
+pub fn weighted_reverse_impact(app: &App, crate_id: i32) -> Pending<i64> {
+    postgres(app, move |conn| super::weighted_reverse_impact(conn, crate_id))
+}
+
+/// Fetches a page of reverse dependencies, like `Crate::reverse_dependencies`.
+/// That query hasn't been ported to diesel yet, so this uses the
+/// `rust-postgres` pool instead.
+pub fn reverse_dependencies(app: &App, crate_id: i32, offset: i64, limit: i64)
+                            -> Pending<(Vec<ReverseDependency>, i64)> {
+    postgres(app, move |conn| {
+        let krate = Crate::find(conn, crate_id)?;
+        krate.reverse_dependencies(conn, offset, limit)
+    })
+}
@@ -0,0 +1,450 @@
+//! A parser for the `target` field of dependencies, following the grammar
+//! Cargo accepts for `[target.'cfg(...)'.dependencies]` sections.
+//!
+//! A target is either a plain target triple such as `x86_64-pc-windows-gnu`,
+//! or a `cfg()` expression built out of names (`unix`), key/value pairs
+//! (`target_os = "linux"`) and the `all`, `any` and `not` combinators.
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Platform {
+    Name(String),
+    Cfg(CfgExpr),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CfgExpr {
+    Not(Box<CfgExpr>),
+    All(Vec<CfgExpr>),
+    Any(Vec<CfgExpr>),
+    Value(Cfg),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cfg {
+    Name(String),
+    KeyPair(String, String),
+}
+
+/// A `CfgExpr` as a tree for the frontend. `op` is one of `not`, `all` and
+/// `any`, with the operands in `args`, or `name` or `key_pair` for a single
+/// predicate, which fills in `name` and, for a key/value pair, `value`.
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug, PartialEq)]
+pub struct EncodableCfg {
+    pub op: String,
+    pub args: Vec<EncodableCfg>,
+    pub name: Option<String>,
+    pub value: Option<String>,
+}
+
+impl Platform {
+    pub fn parse(s: &str) -> Result<Platform, String> {
+        let s = s.trim();
+        if s.starts_with("cfg(") && s.ends_with(')') {
+            let expr = CfgExpr::parse(&s[4..s.len() - 1])?;
+            Ok(Platform::Cfg(expr))
+        } else if s.is_empty() || s.contains(|c: char| c.is_whitespace() || "()=,\"".contains(c)) {
+            Err(format!("`{}` is neither a target triple nor a `cfg()` expression", s))
+        } else {
+            Ok(Platform::Name(s.to_string()))
+        }
+    }
+}
+
+impl CfgExpr {
+    pub fn parse(s: &str) -> Result<CfgExpr, String> {
+        let mut parser = Parser { s: s, tokens: Tokenizer::new(s).peekable() };
+        let expr = parser.expr()?;
+        match parser.tokens.next() {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected `{}` in `cfg({})`", token?, s)),
+        }
+    }
+
+    /// Evaluates this expression against the set of cfg values enabled for
+    /// some platform
+    pub fn matches(&self, cfg: &[Cfg]) -> bool {
+        match *self {
+            CfgExpr::Not(ref e) => !e.matches(cfg),
+            CfgExpr::All(ref e) => e.iter().all(|e| e.matches(cfg)),
+            CfgExpr::Any(ref e) => e.iter().any(|e| e.matches(cfg)),
+            CfgExpr::Value(ref e) => cfg.contains(e),
+        }
+    }
+
+    pub fn encodable(&self) -> EncodableCfg {
+        fn node(op: &str, args: Vec<EncodableCfg>) -> EncodableCfg {
+            EncodableCfg { op: op.to_string(), args: args, name: None, value: None }
+        }
+
+        match *self {
+            CfgExpr::Not(ref e) => node("not", vec![e.encodable()]),
+            CfgExpr::All(ref e) => node("all", e.iter().map(|e| e.encodable()).collect()),
+            CfgExpr::Any(ref e) => node("any", e.iter().map(|e| e.encodable()).collect()),
+            CfgExpr::Value(Cfg::Name(ref name)) => EncodableCfg {
+                name: Some(name.clone()),
+                ..node("name", Vec::new())
+            },
+            CfgExpr::Value(Cfg::KeyPair(ref key, ref value)) => EncodableCfg {
+                name: Some(key.clone()),
+                value: Some(value.clone()),
+                ..node("key_pair", Vec::new())
+            },
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Platform::Name(ref s) => s.fmt(f),
+            Platform::Cfg(ref e) => write!(f, "cfg({})", e),
+        }
+    }
+}
+
+impl fmt::Display for CfgExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn list(f: &mut fmt::Formatter, name: &str, exprs: &[CfgExpr]) -> fmt::Result {
+            write!(f, "{}(", name)?;
+            for (i, e) in exprs.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", e)?;
+            }
+            write!(f, ")")
+        }
+
+        match *self {
+            CfgExpr::Not(ref e) => write!(f, "not({})", e),
+            CfgExpr::All(ref e) => list(f, "all", e),
+            CfgExpr::Any(ref e) => list(f, "any", e),
+            CfgExpr::Value(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for Cfg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Cfg::Name(ref s) => s.fmt(f),
+            Cfg::KeyPair(ref k, ref v) => write!(f, "{} = \"{}\"", k, v),
+        }
+    }
+}
+
+/// A platform we consider common enough that most users of a crate will be
+/// building for it
+pub struct KnownPlatform {
+    pub triple: &'static str,
+    pub family: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub env: &'static str,
+    pub pointer_width: &'static str,
+}
+
+macro_rules! platform {
+    ($triple:expr, $family:expr, $os:expr, $arch:expr, $env:expr, $width:expr) => {
+        KnownPlatform {
+            triple: $triple,
+            family: $family,
+            os: $os,
+            arch: $arch,
+            env: $env,
+            pointer_width: $width,
+        }
+    }
+}
+
+/// The tier 1 platforms, along with a few tier 2 platforms that see a lot of
+/// use. Dependencies that apply to none of these only matter on rare targets.
+pub static COMMON_PLATFORMS: &'static [KnownPlatform] = &[
+    platform!("i686-apple-darwin", "unix", "macos", "x86", "", "32"),
+    platform!("i686-pc-windows-gnu", "windows", "windows", "x86", "gnu", "32"),
+    platform!("i686-pc-windows-msvc", "windows", "windows", "x86", "msvc", "32"),
+    platform!("i686-unknown-linux-gnu", "unix", "linux", "x86", "gnu", "32"),
+    platform!("x86_64-apple-darwin", "unix", "macos", "x86_64", "", "64"),
+    platform!("x86_64-pc-windows-gnu", "windows", "windows", "x86_64", "gnu", "64"),
+    platform!("x86_64-pc-windows-msvc", "windows", "windows", "x86_64", "msvc", "64"),
+    platform!("x86_64-unknown-linux-gnu", "unix", "linux", "x86_64", "gnu", "64"),
+    platform!("aarch64-unknown-linux-gnu", "unix", "linux", "aarch64", "gnu", "64"),
+    platform!("arm-unknown-linux-gnueabihf", "unix", "linux", "arm", "gnu", "32"),
+    platform!("x86_64-unknown-linux-musl", "unix", "linux", "x86_64", "musl", "64"),
+    platform!("arm-linux-androideabi", "unix", "android", "arm", "", "32"),
+    platform!("aarch64-apple-ios", "unix", "ios", "aarch64", "", "64"),
+];
+
+impl KnownPlatform {
+    /// The cfg values rustc sets when compiling for this platform
+    pub fn cfg(&self) -> Vec<Cfg> {
+        let pair = |k: &str, v: &str| Cfg::KeyPair(k.to_string(), v.to_string());
+        vec![
+            Cfg::Name(self.family.to_string()),
+            pair("target_family", self.family),
+            pair("target_os", self.os),
+            pair("target_arch", self.arch),
+            pair("target_env", self.env),
+            pair("target_pointer_width", self.pointer_width),
+            pair("target_endian", "little"),
+        ]
+    }
+}
+
+/// Returns whether a dependency declared for `target` is used when building
+/// for `platform`
+pub fn target_matches(target: &Platform, platform: &KnownPlatform) -> bool {
+    match *target {
+        Platform::Name(ref triple) => triple == platform.triple,
+        Platform::Cfg(ref expr) => expr.matches(&platform.cfg()),
+    }
+}
+
+/// Returns the triples of the common platforms a target applies to
+pub fn target_platforms(target: &Platform) -> Vec<&'static str> {
+    COMMON_PLATFORMS.iter()
+        .filter(|p| target_matches(target, p))
+        .map(|p| p.triple)
+        .collect()
+}
+
+/// Whether a target can only match while building tests or with debug
+/// assertions enabled, so a dependency using it is a dev dependency in all
+/// but name
+pub fn only_in_test_or_debug(target: &Platform) -> bool {
+    let expr = match *target {
+        Platform::Cfg(ref expr) => expr,
+        Platform::Name(_) => return false,
+    };
+    let profiles = [Cfg::Name("test".to_string()), Cfg::Name("debug_assertions".to_string())];
+    COMMON_PLATFORMS.iter().all(|p| !expr.matches(&p.cfg())) &&
+        COMMON_PLATFORMS.iter().any(|p| {
+            let mut cfg = p.cfg();
+            cfg.extend(profiles.iter().cloned());
+            expr.matches(&cfg)
+        })
+}
+
+impl<'a> fmt::Display for Token<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::LeftParen => "(".fmt(f),
+            Token::RightParen => ")".fmt(f),
+            Token::Comma => ",".fmt(f),
+            Token::Equals => "=".fmt(f),
+            Token::Ident(s) => s.fmt(f),
+            Token::String(s) => write!(f, "\"{}\"", s),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Token<'a> {
+    LeftParen,
+    RightParen,
+    Comma,
+    Equals,
+    Ident(&'a str),
+    String(&'a str),
+}
+
+struct Tokenizer<'a> {
+    s: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(s: &'a str) -> Tokenizer<'a> {
+        Tokenizer { s: s, chars: s.char_indices().peekable() }
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Result<Token<'a>, String>;
+
+    fn next(&mut self) -> Option<Result<Token<'a>, String>> {
+        loop {
+            let (start, c) = match self.chars.next() {
+                Some(pair) => pair,
+                None => return None,
+            };
+            let token = match c {
+                ' ' | '\t' | '\n' => continue,
+                '(' => Token::LeftParen,
+                ')' => Token::RightParen,
+                ',' => Token::Comma,
+                '=' => Token::Equals,
+                '"' => {
+                    let end = match self.chars.find(|&(_, c)| c == '"') {
+                        Some((end, _)) => end,
+                        None => return Some(Err("unterminated string".to_string())),
+                    };
+                    Token::String(&self.s[start + 1..end])
+                }
+                c if c == '_' || c.is_alphabetic() => {
+                    let mut end = start + c.len_utf8();
+                    while let Some(&(i, c)) = self.chars.peek() {
+                        if c != '_' && !c.is_alphanumeric() {
+                            break
+                        }
+                        end = i + c.len_utf8();
+                        self.chars.next();
+                    }
+                    Token::Ident(&self.s[start..end])
+                }
+                c => return Some(Err(format!("unexpected character `{}`", c))),
+            };
+            return Some(Ok(token))
+        }
+    }
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    tokens: Peekable<Tokenizer<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn expr(&mut self) -> Result<CfgExpr, String> {
+        let name = match self.tokens.next() {
+            Some(Ok(Token::Ident(name))) => name,
+            Some(Ok(token)) => return Err(format!("expected an identifier but found \
+                                                   `{}` in `cfg({})`", token, self.s)),
+            Some(Err(e)) => return Err(e),
+            None => return Err(format!("expected an identifier in `cfg({})`", self.s)),
+        };
+
+        if self.eat(Token::LeftParen) {
+            let mut exprs = Vec::new();
+            while !self.eat(Token::RightParen) {
+                exprs.push(self.expr()?);
+                if !self.eat(Token::Comma) {
+                    self.expect(Token::RightParen)?;
+                    break
+                }
+            }
+            return match name {
+                "all" => Ok(CfgExpr::All(exprs)),
+                "any" => Ok(CfgExpr::Any(exprs)),
+                "not" if exprs.len() == 1 => Ok(CfgExpr::Not(Box::new(exprs.pop().unwrap()))),
+                "not" => Err(format!("`not()` takes exactly one argument in `cfg({})`", self.s)),
+                _ => Err(format!("unknown predicate `{}()` in `cfg({})`", name, self.s)),
+            }
+        }
+
+        // Cargo doesn't give `true` and `false` any special meaning, so they
+        // only ever show up by mistake
+        if name == "true" || name == "false" {
+            return Err(format!("boolean literal `{}` is not allowed in `cfg({})`",
+                               name, self.s))
+        }
+
+        if self.eat(Token::Equals) {
+            match self.tokens.next() {
+                Some(Ok(Token::String(value))) => {
+                    Ok(CfgExpr::Value(Cfg::KeyPair(name.to_string(), value.to_string())))
+                }
+                Some(Err(e)) => Err(e),
+                _ => Err(format!("expected a string after `{} =` in `cfg({})`", name, self.s)),
+            }
+        } else {
+            Ok(CfgExpr::Value(Cfg::Name(name.to_string())))
+        }
+    }
+
+    fn eat(&mut self, token: Token) -> bool {
+        match self.tokens.peek() {
+            Some(&Ok(t)) if t == token => {
+                self.tokens.next();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), String> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(format!("expected `{}` in `cfg({})`", token, self.s))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_expressions() {
+        let platform = Platform::parse(r#"cfg(all(unix, target_pointer_width = "64"))"#);
+        let expected = CfgExpr::All(vec![
+            CfgExpr::Value(Cfg::Name("unix".to_string())),
+            CfgExpr::Value(Cfg::KeyPair("target_pointer_width".to_string(),
+                                        "64".to_string())),
+        ]);
+        assert_eq!(Ok(Platform::Cfg(expected)), platform);
+    }
+
+    #[test]
+    fn parses_target_triples() {
+        assert_eq!(Ok(Platform::Name("x86_64-pc-windows-gnu".to_string())),
+                   Platform::parse("x86_64-pc-windows-gnu"));
+    }
+
+    #[test]
+    fn rejects_boolean_literals() {
+        let err = Platform::parse("cfg(true)").unwrap_err();
+        assert!(err.contains("boolean literal `true`"), "{}", err);
+        let err = Platform::parse("cfg(not(false))").unwrap_err();
+        assert!(err.contains("boolean literal `false`"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_unknown_predicates() {
+        let err = Platform::parse("cfg(foo(bar))").unwrap_err();
+        assert!(err.contains("unknown predicate `foo()`"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        assert!(Platform::parse("cfg(unix").is_err());
+        assert!(Platform::parse("cfg(target_os = linux)").is_err());
+        assert!(Platform::parse("cfg(not(unix, windows))").is_err());
+    }
+
+    #[test]
+    fn target_platforms_evaluates_cfg() {
+        let unix = Platform::parse("cfg(unix)").unwrap();
+        assert!(target_platforms(&unix).contains(&"x86_64-unknown-linux-gnu"));
+        assert!(!target_platforms(&unix).contains(&"x86_64-pc-windows-msvc"));
+
+        let msvc = Platform::parse(r#"cfg(all(windows, target_env = "msvc"))"#).unwrap();
+        assert_eq!(vec!["i686-pc-windows-msvc", "x86_64-pc-windows-msvc"],
+                   target_platforms(&msvc));
+
+        let redox = Platform::parse(r#"cfg(target_os = "redox")"#).unwrap();
+        assert!(target_platforms(&redox).is_empty());
+    }
+
+    #[test]
+    fn display_is_canonical() {
+        let platform = Platform::parse(r#"cfg(all( unix,target_os="linux",))"#).unwrap();
+        assert_eq!(r#"cfg(all(unix, target_os = "linux"))"#, platform.to_string());
+    }
+
+    #[test]
+    fn only_in_test_or_debug_needs_a_profile_cfg() {
+        let check = |s| only_in_test_or_debug(&Platform::parse(s).unwrap());
+        assert!(check("cfg(test)"));
+        assert!(check("cfg(all(unix, debug_assertions))"));
+        assert!(!check("cfg(not(test))"));
+        assert!(!check("cfg(any(test, unix))"));
+        assert!(!check(r#"cfg(all(test, target_os = "redox"))"#));
+        assert!(!check("x86_64-unknown-linux-gnu"));
+    }
+}
@@ -0,0 +1,64 @@
+//! Lints for a single dependency's requirement and configuration, which
+//! don't stop a publish but are usually not what the author meant.
+
+use super::{Dependency, req};
+use super::req::Op;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, RustcEncodable)]
+pub enum HygieneCode {
+    /// `=1.2.3`, which keeps the dependency from ever being updated
+    ExactPin,
+    /// `>= 1.2`, which accepts future, breaking, major versions
+    NoUpperBound,
+    /// `^0.3`, which only allows patch updates
+    TightPreOneCaret,
+    /// `>= 0`, which is `*` in all but name
+    MatchesEverything,
+    /// Enabling the `default` feature, which default features already are
+    RedundantDefaultFeatures,
+}
+
+#[derive(Clone, Debug, PartialEq, RustcEncodable)]
+pub struct HygieneIssue {
+    pub code: HygieneCode,
+    pub message: String,
+}
+
+pub fn issues(dep: &Dependency) -> Vec<HygieneIssue> {
+    fn issue(code: HygieneCode, message: String) -> HygieneIssue {
+        HygieneIssue { code: code, message: message }
+    }
+
+    let mut issues = Vec::new();
+    if req::comparators(&dep.req).iter().any(|c| c.op == Op::Exact) {
+        issues.push(issue(HygieneCode::ExactPin,
+                          format!("`{}` pins an exact version, so compatible updates \
+                                   are never picked up", dep.req)));
+    }
+    if dep.is_pre_one_caret() {
+        issues.push(issue(HygieneCode::TightPreOneCaret,
+                          format!("`{}` is a caret requirement on a 0.x version, which \
+                                   only allows patch updates", dep.req)));
+    }
+    if req::req_upper_bound(&dep.req).is_none() {
+        let zero = ::semver::Version::parse("0.0.0").unwrap();
+        match req::req_lower_bound(&dep.req) {
+            Some(ref lower) if *lower > zero => {
+                issues.push(issue(HygieneCode::NoUpperBound,
+                                  format!("`{}` has no upper bound, so it accepts \
+                                           breaking releases", dep.req)));
+            }
+            _ => {
+                issues.push(issue(HygieneCode::MatchesEverything,
+                                  format!("`{}` matches every version, like `*`",
+                                          dep.req)));
+            }
+        }
+    }
+    if dep.default_features && dep.features.iter().any(|f| f == "default") {
+        issues.push(issue(HygieneCode::RedundantDefaultFeatures,
+                          "the `default` feature is enabled, but default features \
+                           already are".to_string()));
+    }
+    issues
+}
@@ -0,0 +1,3523 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use diesel::prelude::*;
+use diesel::pg::{Pg, PgConnection};
+use pg::GenericConnection;
+use pg::rows::Row;
+use semver;
+
+use Model;
+use git;
+use krate::{Crate, canon_crate_name};
+use schema::*;
+use util::{CargoError, CargoResult, human, internal};
+
+pub use self::audit::{AuditAction, AuditEvent, AuditHook, Signal};
+pub use self::hygiene::{HygieneCode, HygieneIssue};
+pub use self::policy::{DependencyAllowlist, DependencyInterceptor, DependencyPolicy, FacadeCrates,
+                       InterceptAction, KindPolicy, MsrvSource, Severity};
+pub use self::req::{Comparator, Op};
+
+pub mod audit;
+pub mod background;
+pub mod cfg;
+pub mod hygiene;
+pub mod policy;
+pub mod req;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dependency {
+    pub id: i32,
+    pub version_id: i32,
+    pub crate_id: i32,
+    pub req: semver::VersionReq,
+    pub optional: bool,
+    pub default_features: bool,
+    pub features: Vec<String>,
+    pub target: Option<String>,
+    pub kind: Kind,
+    /// Set when `req` was stored in a form only the legacy parser accepts,
+    /// and needs migrating to its canonical form. This isn't a column.
+    pub req_was_shimmed: bool,
+    /// The requirement exactly as it's stored. This is the canonical form of
+    /// `req` unless `req_is_raw` is set.
+    pub raw_req: String,
+    /// Set when the requirement was stored as the author wrote it, because
+    /// the crate was published under `preserve_raw_req`
+    pub req_is_raw: bool,
+}
+
+pub struct ReverseDependency {
+    dependency: Dependency,
+    crate_name: String,
+    crate_downloads: i32,
+}
+
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct EncodableDependency {
+    pub id: i32,
+    pub version_id: i32,
+    pub crate_id: String,
+    pub req: String,
+    pub optional: bool,
+    pub default_features: bool,
+    pub features: Vec<String>,
+    pub target: Option<String>,
+    /// The parsed form of `target`, if it's a `cfg()` expression
+    pub target_cfg: Option<cfg::EncodableCfg>,
+    pub kind: Kind,
+    pub downloads: i32,
+}
+
+/// A lighter version of `EncodableDependency` for views listing many
+/// dependencies at once
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct EncodableDependencySummary {
+    pub crate_name: String,
+    pub req: String,
+    pub kind: Kind,
+    pub optional: bool,
+}
+
+/// How many dependencies a version pulls in, broken down by kind
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug, Default, PartialEq)]
+pub struct DependencySurface {
+    pub normal: usize,
+    pub build: usize,
+    pub dev: usize,
+    pub optional: usize,
+    pub platform_specific: usize,
+    pub distinct_crates: usize,
+}
+
+/// The dependencies of a batch of versions as one column per field, for bulk
+/// exports to columnar formats. Row `i` of the batch is made up of element
+/// `i` of every column.
+#[derive(RustcEncodable, Clone, Debug, Default, PartialEq)]
+pub struct DependencyColumns {
+    pub version_id: Vec<i32>,
+    pub crate_name: Vec<String>,
+    pub req: Vec<String>,
+    /// See `Kind::stable_code`
+    pub kind_code: Vec<u8>,
+    pub optional: Vec<bool>,
+    pub default_features: Vec<bool>,
+    pub target: Vec<Option<String>>,
+}
+
+/// Whether a dependency's requirement accepts a prospective new version of
+/// the crate depended upon
+#[derive(Clone, Debug, PartialEq)]
+pub enum CompatNote {
+    Compatible,
+    /// The requirement has to be changed to this one to accept the version
+    BumpTo(semver::VersionReq),
+}
+
+/// A dependency of a version along with the version of the crate depended
+/// upon that it currently resolves to
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedEdge {
+    pub name: String,
+    pub req: String,
+    pub kind: Kind,
+    /// The newest non-yanked version matching `req`, if there is one
+    pub resolved_version: Option<semver::Version>,
+}
+
+/// The crates which one version depends on and another doesn't, by id
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DependencyDiff {
+    pub added: Vec<i32>,
+    pub removed: Vec<i32>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum Kind {
+    Normal = 0,
+    Build = 1,
+    Dev = 2,
+    // if you add a kind here, be sure to update `from_row` below.
+}
+
+impl Kind {
+    /// The name of this kind as it appears in Cargo manifests and the index
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Kind::Normal => "normal",
+            Kind::Build => "build",
+            Kind::Dev => "dev",
+        }
+    }
+
+    /// A code for this kind which is guaranteed to never change, for use by
+    /// consumers outside crates.io such as analytics pipelines. Unlike the
+    /// value stored in the database, it isn't tied to how the kind is
+    /// represented internally.
+    ///
+    /// The mapping is `1` for normal, `2` for build and `3` for dev. Codes
+    /// are never reused, and new kinds will get new codes.
+    pub fn stable_code(&self) -> u8 {
+        match *self {
+            Kind::Normal => 1,
+            Kind::Build => 2,
+            Kind::Dev => 3,
+        }
+    }
+
+    /// The inverse of `stable_code`, returning `None` for unknown codes
+    pub fn from_stable_code(code: u8) -> Option<Kind> {
+        match code {
+            1 => Some(Kind::Normal),
+            2 => Some(Kind::Build),
+            3 => Some(Kind::Dev),
+            _ => None,
+        }
+    }
+}
+
+/// The feature resolver a crate asked for in its manifest
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Resolver {
+    V1,
+    /// Optional dependencies don't get an implicit feature of the same name,
+    /// so they have to be enabled explicitly with `dep:name`
+    V2,
+}
+
+impl Resolver {
+    pub fn from_manifest(resolver: Option<&str>) -> CargoResult<Resolver> {
+        match resolver {
+            None | Some("1") => Ok(Resolver::V1),
+            Some("2") => Ok(Resolver::V2),
+            Some(s) => Err(human(&format_args!("unknown resolver `{}`", s))),
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[table_name="dependencies"]
+struct NewDependency<'a> {
+    version_id: i32,
+    crate_id: i32,
+    req: String,
+    optional: bool,
+    default_features: bool,
+    features: Vec<&'a str>,
+    target: Option<&'a str>,
+    kind: i32,
+    req_is_raw: bool,
+}
+
+impl Dependency {
+    // FIXME: Encapsulate this in a `NewDependency` struct
+    #[cfg_attr(feature = "clippy", allow(too_many_arguments))]
+    pub fn insert(conn: &GenericConnection, version_id: i32, crate_id: i32,
+                  req: &semver::VersionReq, kind: Kind,
+                  optional: bool, default_features: bool,
+                  features: &[String], target: &Option<String>)
+                  -> CargoResult<Dependency> {
+        let req = req.to_string();
+        let stmt = conn.prepare("INSERT INTO dependencies
+                                      (version_id, crate_id, req, optional,
+                                       default_features, features, target, kind)
+                                      VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                                      RETURNING *")?;
+        let rows = stmt.query(&[&version_id, &crate_id, &req,
+            &optional, &default_features,
+            &features, target, &(kind as i32)])?;
+        Ok(Model::from_row(&rows.iter().next().unwrap()))
+    }
+
+    /// Puts this dependency into canonical form, so that two dependencies
+    /// which mean the same thing also compare equal: features are sorted and
+    /// deduplicated, and the target and requirement use their canonical
+    /// spelling.
+    pub fn normalize(&mut self) {
+        self.features.sort();
+        self.features.dedup();
+        self.target = self.target.take().map(|target| normalize_target(&target));
+        if let Ok(req) = semver::VersionReq::parse(&self.req.to_string()) {
+            self.req = req;
+        }
+        self.raw_req = self.req.to_string();
+        self.req_is_raw = false;
+    }
+
+    /// What makes this dependency unique within its version: a crate may
+    /// only be depended upon once per kind and target
+    pub fn dedup_key(&self) -> (i32, Kind, Option<String>) {
+        dedup_key(self.crate_id, self.kind, self.target.as_ref().map(|t| &**t))
+    }
+
+    /// Returns the operator of each comparator in this dependency's
+    /// requirement, in the order they were written
+    pub fn req_operators(&self) -> Vec<Op> {
+        req::comparators(&self.req).into_iter().map(|c| c.op).collect()
+    }
+
+    /// Returns whether the requirement is a caret requirement on a `0.x`
+    /// version. Those only allow patch updates (or none at all for `^0.0.x`),
+    /// so they're a lot tighter than they look.
+    pub fn is_pre_one_caret(&self) -> bool {
+        req::comparators(&self.req).iter().any(|c| {
+            c.op == Op::Caret && c.major == Some(0)
+        })
+    }
+
+    /// Everything about this dependency's requirement and configuration that
+    /// looks like a mistake, such as exact pins or missing upper bounds
+    pub fn hygiene_issues(&self) -> Vec<HygieneIssue> {
+        hygiene::issues(self)
+    }
+
+    /// Whether `version` of the crate depended upon meets the requirement
+    pub fn satisfied_by(&self, version: &semver::Version) -> bool {
+        self.req.matches(version)
+    }
+
+    /// Works out whether `candidate` would be accepted as it stands, or which
+    /// requirement would. The suggestion is a caret requirement on the
+    /// candidate, so it still accepts compatible releases after it.
+    pub fn compatibility_note(&self, candidate: &semver::Version) -> CompatNote {
+        if self.satisfied_by(candidate) {
+            return CompatNote::Compatible;
+        }
+        let mut bare = candidate.clone();
+        bare.build.clear();
+        let suggested = semver::VersionReq::parse(&format!("^{}", bare))
+            .expect("a caret on a version is always a valid requirement");
+        CompatNote::BumpTo(suggested)
+    }
+
+    /// Renders this dependency as arguments to `cargo add`, such as
+    /// `foo@^1.0 --features a,b --optional`. Flags matching Cargo's defaults
+    /// are left out, and arguments are quoted where a shell would need it.
+    pub fn to_cargo_add_spec(&self, crate_name: &str) -> String {
+        fn quote(s: &str) -> String {
+            if s.chars().all(|c| c.is_alphanumeric() || "_-.@^~=,/:".contains(c)) {
+                s.to_string()
+            } else {
+                format!("'{}'", s.replace('\'', "'\\''"))
+            }
+        }
+
+        let mut spec = quote(&format!("{}@{}", crate_name, self.published_req()));
+        match self.kind {
+            Kind::Normal => {}
+            Kind::Build => spec.push_str(" --build"),
+            Kind::Dev => spec.push_str(" --dev"),
+        }
+        if !self.default_features {
+            spec.push_str(" --no-default-features");
+        }
+        if !self.features.is_empty() {
+            spec.push_str(" --features ");
+            spec.push_str(&quote(&self.features.join(",")));
+        }
+        if self.optional {
+            spec.push_str(" --optional");
+        }
+        if let Some(ref target) = self.target {
+            spec.push_str(" --target ");
+            spec.push_str(&quote(target));
+        }
+        spec
+    }
+
+    /// A one-line description of this dependency, such as `foo ^1.0 (dev)` or
+    /// `foo ^1.0 (normal, cfg(unix))`
+    pub fn summary(&self, crate_name: &str) -> String {
+        match self.target {
+            Some(ref target) => {
+                format!("{} {} ({}, {})", crate_name, self.published_req(), self.kind.name(),
+                        target)
+            }
+            None => format!("{} {} ({})", crate_name, self.published_req(), self.kind.name()),
+        }
+    }
+
+    /// The requirement to show to the outside world, which everything
+    /// exposing a dependency goes through. Requirements stored verbatim are
+    /// shown that way, unless they needed the legacy parser, since then they
+    /// wouldn't parse as written.
+    pub fn published_req(&self) -> String {
+        if self.req_is_raw && !self.req_was_shimmed {
+            self.raw_req.clone()
+        } else {
+            self.index_req_string()
+        }
+    }
+
+    /// The requirement formatted exactly like Cargo formats it in the index
+    pub fn index_req_string(&self) -> String {
+        req::index_string(&self.req)
+    }
+
+    pub fn git_encode(self, crate_name: &str) -> git::Dependency {
+        // Cargo can't use an index entry whose requirement doesn't parse,
+        // so fall back to semver's own formatting rather than publish one
+        let mut req = self.published_req();
+        if semver::VersionReq::parse(&req).is_err() {
+            debug_assert!(false, "`{}` for `{}` doesn't parse", req, crate_name);
+            error!("index requirement `{}` for `{}` doesn't parse, using `{}` instead",
+                   req, crate_name, self.req);
+            req = self.req.to_string();
+        }
+        git::Dependency {
+            name: crate_name.into(),
+            req: req,
+            features: self.features,
+            optional: self.optional,
+            default_features: self.default_features,
+            target: self.target,
+            kind: Some(self.kind),
+        }
+    }
+
+    /// This dependency's entry in the index, as the JSON written there
+    pub fn to_index_value(&self, crate_name: &str) -> String {
+        use rustc_serialize::json;
+
+        json::encode(&self.clone().git_encode(crate_name)).unwrap()
+    }
+
+    // `downloads` need only be specified when generating a reverse dependency
+    pub fn encodable(self, crate_name: &str, downloads: Option<i32>) -> EncodableDependency {
+        let target_cfg = match self.target.as_ref().map(|t| cfg::Platform::parse(t)) {
+            Some(Ok(cfg::Platform::Cfg(expr))) => Some(expr.encodable()),
+            _ => None,
+        };
+        EncodableDependency {
+            id: self.id,
+            version_id: self.version_id,
+            crate_id: crate_name.into(),
+            req: self.published_req(),
+            optional: self.optional,
+            default_features: self.default_features,
+            features: self.features,
+            target: self.target,
+            target_cfg: target_cfg,
+            kind: self.kind,
+            downloads: downloads.unwrap_or(0),
+        }
+    }
+
+    pub fn encodable_summary(&self, crate_name: &str) -> EncodableDependencySummary {
+        EncodableDependencySummary {
+            crate_name: crate_name.into(),
+            req: self.published_req(),
+            kind: self.kind,
+            optional: self.optional,
+        }
+    }
+}
+
+/// `canon_crate_name` is unique in the database, so this should never fail,
+/// but picking one of the rows at random would link a dependency to the
+/// wrong crate
+fn check_unique_canonical_names(crates: &[Crate]) -> CargoResult<()> {
+    let mut canon_names = HashSet::new();
+    for krate in crates {
+        if !canon_names.insert(canonical_crate_name(&krate.name)) {
+            return Err(internal(&format_args!("multiple crates share the \
+                                               canonical name of `{}`",
+                                              krate.name)));
+        }
+    }
+    Ok(())
+}
+
+/// The name as `canon_crate_name` sees it, which is what makes two crate
+/// names the same crate
+fn canonical_crate_name(name: &str) -> String {
+    name.to_lowercase().replace('-', "_")
+}
+
+fn dedup_key(crate_id: i32, kind: Kind, target: Option<&str>) -> (i32, Kind, Option<String>) {
+    (crate_id, kind, target.map(normalize_target))
+}
+
+/// The canonical spelling of a target, so `cfg( unix )` and `cfg(unix)` are
+/// treated the same
+fn normalize_target(target: &str) -> String {
+    match cfg::Platform::parse(target) {
+        Ok(platform) => platform.to_string(),
+        Err(_) => target.trim().to_string(),
+    }
+}
+
+impl ReverseDependency {
+    pub fn encodable(self) -> EncodableDependency {
+        self.dependency.encodable(&self.crate_name, Some(self.crate_downloads))
+    }
+}
+
+/// Inserts the dependencies of a newly published version, returning the new
+/// rows along with any warnings that should be reported back to the user
+pub fn add_dependencies(
+    conn: &PgConnection,
+    deps: &[::upload::CrateDependency],
+    features: &HashMap<::upload::CrateName, Vec<::upload::Feature>>,
+    resolver: Resolver,
+    version_id: i32,
+    policy: &DependencyPolicy,
+) -> CargoResult<(Vec<Dependency>, Vec<String>)> {
+    use diesel::insert;
+
+    let deps = intercept(deps, policy)?;
+    // If the caller already started a transaction this becomes a savepoint,
+    // either way a failure never leaves some of the rows behind
+    conn.transaction(|| {
+        let (new_dependencies, warnings) =
+            new_dependencies(conn, &deps, features, resolver, version_id, policy)?;
+        let dependencies = insert(&new_dependencies).into(dependencies::table)
+            .get_results(conn)?;
+        Ok((dependencies, warnings))
+    })
+}
+
+/// Replaces every dependency of a version with `deps`, which are checked like
+/// the dependencies of a new version under `policy`. This is meant for
+/// administrative corrections, so warnings are discarded.
+pub fn replace_dependencies(conn: &PgConnection, version_id: i32,
+                            deps: &[::upload::CrateDependency],
+                            policy: &DependencyPolicy)
+                            -> CargoResult<Vec<Dependency>> {
+    use diesel::delete;
+
+    conn.transaction(|| {
+        delete(dependencies::table.filter(dependencies::version_id.eq(version_id)))
+            .execute(conn)?;
+        let (dependencies, _) = add_dependencies(conn, deps, &HashMap::new(), Resolver::V1,
+                                                 version_id, policy)?;
+        Ok(dependencies)
+    })
+}
+
+/// Runs `replace_dependencies` for several versions of `crate_id` at once.
+/// If any of the versions fails, none of them are changed.
+pub fn bulk_replace_for_crate(conn: &PgConnection, crate_id: i32,
+                              version_deps: &HashMap<i32, Vec<::upload::CrateDependency>>,
+                              policy: &DependencyPolicy)
+                              -> CargoResult<()> {
+    use diesel::expression::dsl::any;
+
+    let mut version_ids = version_deps.keys().cloned().collect::<Vec<_>>();
+    version_ids.sort();
+    conn.transaction(|| {
+        let owned = versions::table
+            .filter(versions::id.eq(any(&version_ids[..])))
+            .filter(versions::crate_id.eq(crate_id))
+            .select(versions::id)
+            .load::<i32>(conn)?;
+        if let Some(id) = version_ids.iter().find(|&&id| !owned.contains(&id)) {
+            return Err(human(&format_args!("version {} doesn't belong to crate {}",
+                                           id, crate_id)));
+        }
+        for version_id in &version_ids {
+            replace_dependencies(conn, *version_id, &version_deps[version_id], policy)?;
+        }
+        Ok(())
+    })
+}
+
+/// Runs the policy's interceptor, if any, over each dependency
+fn intercept<'a>(deps: &'a [::upload::CrateDependency], policy: &DependencyPolicy)
+                 -> CargoResult<Cow<'a, [::upload::CrateDependency]>> {
+    let interceptor = match policy.interceptor {
+        Some(ref interceptor) => interceptor,
+        None => return Ok(Cow::Borrowed(deps)),
+    };
+    let mut intercepted = Vec::with_capacity(deps.len());
+    for dep in deps {
+        match interceptor.inspect(dep) {
+            InterceptAction::Accept => intercepted.push(dep.clone()),
+            InterceptAction::Reject(reason) => {
+                return Err(reject(policy, dep, &dep.version_req.1, reason));
+            }
+            InterceptAction::Transform(dep) => intercepted.push(dep),
+        }
+    }
+    Ok(Cow::Owned(intercepted))
+}
+
+/// Like `add_dependencies`, but only returns the ids of the new rows, which
+/// saves decoding them for callers such as data migrations. The dependencies
+/// still go through every check of the default policy, as a version without
+/// any features using the version 1 resolver, and any warnings are discarded.
+pub fn add_dependencies_ids(conn: &PgConnection, deps: &[::upload::CrateDependency],
+                            version_id: i32) -> CargoResult<Vec<i32>> {
+    use diesel::insert;
+
+    conn.transaction(|| {
+        let (new_dependencies, _) = new_dependencies(conn, deps, &HashMap::new(),
+                                                     Resolver::V1, version_id,
+                                                     &DependencyPolicy::default())?;
+        insert(&new_dependencies).into(dependencies::table)
+            .returning(dependencies::id)
+            .get_results(conn)
+            .map_err(Into::into)
+    })
+}
+
+/// Validates the dependencies of a version and turns them into rows ready to
+/// be inserted
+fn new_dependencies<'a>(
+    conn: &PgConnection,
+    deps: &'a [::upload::CrateDependency],
+    features: &HashMap<::upload::CrateName, Vec<::upload::Feature>>,
+    resolver: Resolver,
+    version_id: i32,
+    policy: &DependencyPolicy,
+) -> CargoResult<(Vec<NewDependency<'a>>, Vec<String>)> {
+    use diesel::expression::dsl::any;
+
+    if let Some(baseline) = policy.dependency_baseline {
+        if deps.len() > baseline {
+            policy.signal(Signal::ManyDependencies {
+                version_id: version_id,
+                count: deps.len(),
+                baseline: baseline,
+            });
+        }
+    }
+
+    validate_weak_features(deps, features, policy)?;
+    if let Some(ref allowlist) = policy.allowlist {
+        check_allowlist(deps, &**allowlist, policy)?;
+    }
+
+    let mut warnings = Vec::new();
+    if resolver == Resolver::V2 {
+        check_unreferenced_optional(deps, features, policy, &mut warnings)?;
+    }
+    let mut seen = HashMap::new();
+
+    let crate_names = deps.iter().map(|d| &*d.name).collect::<Vec<_>>();
+    let crates = Crate::all()
+        .filter(canon_crate_name(crates::name).eq(any(crate_names)))
+        .load::<Crate>(conn)?;
+    check_unique_canonical_names(&crates)?;
+
+    // Only the features of the version each dependency would resolve to are
+    // needed, so those are picked out before decoding any
+    let (feature_versions, known_features) = if deps.iter().any(|d| !d.features.is_empty()) {
+        let ids = crates.iter().map(|c| c.id).collect::<Vec<_>>();
+        let versions = versions_with_features(conn, &ids)?;
+        let candidates = deps.iter()
+            .filter(|d| !d.features.is_empty())
+            .filter_map(|d| {
+                dependency_crate(&crates, &d.name)
+                    .and_then(|k| newest_with_features(&versions, k.id, &d.version_req.0))
+            })
+            .map(|&(_, id)| id)
+            .collect::<Vec<_>>();
+        let known = known_features(conn, &candidates)?;
+        (versions, known)
+    } else {
+        (HashMap::new(), HashMap::new())
+    };
+
+    // Only checked when both the version being published and the version
+    // of the dependency Cargo would pick have a known MSRV
+    let msrv = match policy.msrv {
+        Some(ref source) => {
+            let (crate_id, num) = versions::table.find(version_id)
+                .select((versions::crate_id, versions::num))
+                .first::<(i32, String)>(conn)?;
+            let num = semver::Version::parse(&num).unwrap();
+            match source.rust_version(crate_id, &num) {
+                Some(rust_version) => {
+                    let ids = crates.iter().map(|c| c.id).collect::<Vec<_>>();
+                    Some((source, rust_version, published_versions(conn, &ids)?))
+                }
+                None => None,
+            }
+        }
+        None => None,
+    };
+
+    let new_dependencies = deps.iter().map(|dep| {
+        let req = &dep.version_req.1;
+        let krate = dependency_crate(&crates, &dep.name)
+            .map(Ok)
+            .unwrap_or_else(|| {
+                Err(reject(policy, dep, req,
+                           format!("no known crate named `{}` (canonically `{}`)",
+                                   &*dep.name, canonical_crate_name(&dep.name))))
+            })?;
+        if dep.version_req == semver::VersionReq::parse("*").unwrap() {
+            return Err(reject(policy, dep, req,
+                              "wildcard (`*`) dependency constraints are not allowed \
+                               on crates.io. See http://doc.crates.io/faq.html#can-\
+                               libraries-use--as-a-version-for-their-dependencies for \
+                               more information".to_string()));
+        }
+        // Cargo allows a crate to show up once per kind and target, so the
+        // same crate as both a normal and a dev dependency is fine, as is
+        // requiring different versions of it on different platforms
+        let kind = dep.kind.unwrap_or(Kind::Normal);
+        let key = dedup_key(krate.id, kind, dep.target.as_ref().map(|t| &**t));
+        if let Some(other) = seen.insert(key, &dep.version_req) {
+            // Compared as parsed requirements, since `1` and `^1` are the
+            // same requirement written two ways
+            let reason = if req::comparators(&other.0) == req::comparators(&dep.version_req) {
+                format!("dependency `{}` is declared more than once as a {} \
+                         dependency", &*dep.name, kind.name())
+            } else {
+                format!("dependency `{}` is declared more than once as a {} \
+                         dependency, with contradictory requirements `{}` and `{}`",
+                        &*dep.name, kind.name(), other.1, req)
+            };
+            return Err(reject(policy, dep, req, reason));
+        }
+        if let Some(ref allowed) = policy.kind(kind).allowed_ops {
+            let forbidden = req::comparators(&dep.version_req).into_iter()
+                .map(|c| c.op)
+                .find(|op| !allowed.contains(op));
+            if let Some(op) = forbidden {
+                return Err(reject(policy, dep, req,
+                                  format!("{} dependency `{}` uses the `{}` operator, \
+                                           which isn't allowed for {} dependencies",
+                                          kind.name(), &*dep.name, op.symbol(),
+                                          kind.name())));
+            }
+        }
+        if !dep.default_features && dep.features.is_empty() &&
+            policy.kind(kind).deny_bare_no_default_features {
+            return Err(reject(policy, dep, req,
+                              format!("{} dependency `{}` disables default features \
+                                       without enabling any others",
+                                      kind.name(), &*dep.name)));
+        }
+        if dep.features.iter().any(|f| &**f == "default") {
+            if !dep.default_features {
+                return Err(reject(policy, dep, req,
+                                  format!("dependency `{}` disables default features but \
+                                           enables the `default` feature",
+                                          &*dep.name)));
+            }
+            warnings.push(format!("dependency `{}` enables the `default` feature, which \
+                                   is already enabled by default", &*dep.name));
+        }
+        // Only versions published with their features can be checked, and
+        // the newest one the requirement allows is what Cargo would pick
+        let newest = newest_with_features(&feature_versions, krate.id, &dep.version_req.0)
+            .and_then(|&(ref num, id)| known_features.get(&id).map(|defined| (num, defined)));
+        if let Some((num, defined)) = newest {
+            let undefined = dep.features.iter()
+                .map(|f| &**f)
+                .filter(|f| !defined.contains(*f))
+                .collect::<Vec<_>>();
+            if !undefined.is_empty() {
+                return Err(reject(policy, dep, req,
+                                  format!("dependency `{}` enables features which \
+                                           `{}` {} doesn't define: {}", &*dep.name,
+                                          krate.name, num, undefined.join(", "))));
+            }
+        }
+        if let Some(underlying) = policy.facades.as_ref()
+                                        .and_then(|f| f.underlying(&krate.name)) {
+            warnings.push(format!("dependency `{}` only re-exports `{}`, consider \
+                                   depending on `{}` directly",
+                                  &*dep.name, underlying, underlying));
+        }
+        if let Some((ref source, ref rust_version, ref published)) = msrv {
+            let required = published.get(&krate.id)
+                .and_then(|versions| matching_latest(versions, &dep.version_req))
+                .and_then(|newest| source.rust_version(krate.id, newest).map(|r| (newest, r)));
+            if let Some((newest, required)) = required {
+                if required > *rust_version {
+                    let reason = format!("dependency `{}` resolves to {} {}, which needs \
+                                          Rust {} but this crate supports Rust {}",
+                                         &*dep.name, krate.name, newest, required,
+                                         rust_version);
+                    match policy.msrv_mismatch {
+                        Severity::Allow => {}
+                        Severity::Warn => warnings.push(reason),
+                        Severity::Deny => return Err(reject(policy, dep, req, reason)),
+                    }
+                }
+            }
+        }
+        if let Some(ref target) = dep.target {
+            cfg::Platform::parse(target).map_err(|e| {
+                reject(policy, dep, target,
+                       format!("invalid target `{}` for dependency `{}`: {}",
+                               target, &*dep.name, e))
+            })?;
+        }
+        // Pre-releases tend to be yanked once the release is out, which would
+        // leave nothing for this requirement to match
+        if req::comparators(&dep.version_req).iter()
+            .any(|c| c.op == Op::Exact && !c.pre.is_empty()) {
+            warnings.push(format!("dependency `{}` is pinned to the pre-release `{}`, \
+                                   which may not stay available once a stable release \
+                                   is published", &*dep.name, req.trim()));
+        }
+        if dep.version_req.is_padded() {
+            let warning = format!("the version requirement `{}` for dependency \
+                                   `{}` had surrounding whitespace, which was \
+                                   removed", req, &*dep.name);
+            policy.audit(AuditEvent {
+                dependency: dep.name.to_string(),
+                original: req.clone(),
+                action: AuditAction::Normalized,
+                reason: warning.clone(),
+            });
+            warnings.push(warning);
+        }
+        let features = dep.features.iter().map(|s| &**s).collect();
+        Ok(NewDependency {
+            version_id: version_id,
+            crate_id: krate.id,
+            req: if policy.preserve_raw_req {
+                req.trim().to_string()
+            } else {
+                dep.version_req.to_string()
+            },
+            req_is_raw: policy.preserve_raw_req,
+            kind: kind as i32,
+            optional: dep.optional,
+            default_features: dep.default_features,
+            features: features,
+            target: dep.target.as_ref().map(|s| &**s),
+        })
+    }).collect::<Result<Vec<_>, _>>()?;
+
+    let total_features = deps.iter().map(|d| d.features.len()).sum::<usize>();
+    if let Some(max) = policy.max_total_features {
+        if total_features > max {
+            let reason = format!("dependencies enable {} features in total, but at \
+                                  most {} are allowed", total_features, max);
+            policy.audit(AuditEvent::without_dependency(&total_features.to_string(),
+                                                        AuditAction::Rejected, &reason));
+            return Err(human(&reason));
+        }
+    }
+
+    Ok((new_dependencies, warnings))
+}
+
+/// A feature may enable `feat` of a dependency `pkg` only if `pkg` is
+/// enabled some other way by writing `pkg?/feat`, which only makes sense when
+/// `pkg` is an optional dependency
+fn validate_weak_features(deps: &[::upload::CrateDependency],
+                          features: &HashMap<::upload::CrateName, Vec<::upload::Feature>>,
+                          policy: &DependencyPolicy)
+                          -> CargoResult<()> {
+    for (feature, values) in features {
+        for value in values {
+            let pkg = match value.find("?/") {
+                Some(i) => &value[..i],
+                None => continue,
+            };
+            match deps.iter().find(|d| d.name == pkg) {
+                Some(dep) if dep.optional => {}
+                Some(dep) => {
+                    return Err(reject(policy, dep, value, format!("feature `{}` \
+                        includes `{}` with a `?`, but `{}` is not an optional \
+                        dependency", &**feature, &**value, pkg)))
+                }
+                None => {
+                    let reason = format!("feature `{}` includes `{}`, but `{}` is not a \
+                                          dependency", &**feature, &**value, pkg);
+                    policy.audit(AuditEvent::without_dependency(value, AuditAction::Rejected,
+                                                                &reason));
+                    return Err(human(&reason))
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The crate a dependency named `name` refers to. The crates are looked up by
+/// canonical name, so `foo-bar` may have found the crate published as
+/// `foo_bar`, but an exact match wins.
+fn dependency_crate<'a>(crates: &'a [Crate], name: &str) -> Option<&'a Crate> {
+    let canon = canonical_crate_name(name);
+    crates.iter().find(|c| name == c.name)
+        .or_else(|| crates.iter().find(|c| canonical_crate_name(&c.name) == canon))
+}
+
+/// The number and id of each non-yanked version of the given crates which
+/// was published along with its features
+fn versions_with_features(conn: &PgConnection, crate_ids: &[i32])
+                          -> CargoResult<HashMap<i32, Vec<(semver::Version, i32)>>> {
+    use diesel::expression::dsl::any;
+
+    let rows = versions::table
+        .filter(versions::crate_id.eq(any(crate_ids)))
+        .filter(versions::yanked.eq(false))
+        .filter(versions::features.is_not_null())
+        .select((versions::crate_id, versions::num, versions::id))
+        .load::<(i32, String, i32)>(conn)?;
+    let mut versions = HashMap::new();
+    for (crate_id, num, id) in rows {
+        versions.entry(crate_id).or_insert_with(Vec::new)
+            .push((semver::Version::parse(&num).unwrap(), id));
+    }
+    Ok(versions)
+}
+
+/// The newest version of a crate with known features which `req` allows,
+/// which is the one Cargo would pick
+fn newest_with_features<'a>(versions: &'a HashMap<i32, Vec<(semver::Version, i32)>>,
+                            crate_id: i32, req: &semver::VersionReq)
+                            -> Option<&'a (semver::Version, i32)> {
+    versions.get(&crate_id).and_then(|versions| {
+        versions.iter()
+            .filter(|&&(ref num, _)| req.matches(num))
+            .max_by(|a, b| a.0.cmp(&b.0))
+    })
+}
+
+/// The features defined by each of the given versions, including the
+/// implicit ones for optional dependencies
+fn known_features(conn: &PgConnection, version_ids: &[i32])
+                  -> CargoResult<HashMap<i32, HashSet<String>>> {
+    use diesel::expression::dsl::any;
+    use rustc_serialize::json;
+
+    let versions = versions::table
+        .filter(versions::id.eq(any(version_ids)))
+        .select((versions::id, versions::features))
+        .load::<(i32, Option<String>)>(conn)?;
+    let optional = dependencies::table
+        .filter(dependencies::version_id.eq(any(version_ids)))
+        .filter(dependencies::optional.eq(true))
+        .select((dependencies::version_id, dependencies::crate_id))
+        .load::<(i32, i32)>(conn)?;
+    let optional_ids = optional.iter().map(|o| o.1).collect::<Vec<_>>();
+    let names = resolve_names_and_downloads(conn, &optional_ids)?;
+
+    let mut known = HashMap::new();
+    for (id, features) in versions {
+        let features = match features {
+            Some(features) => features,
+            None => continue,
+        };
+        let features: HashMap<String, Vec<String>> = json::decode(&features)?;
+        let mut defined = features.into_iter().map(|(k, _)| k).collect::<HashSet<_>>();
+        defined.extend(optional.iter()
+            .filter(|o| o.0 == id)
+            .filter_map(|o| names.get(&o.1))
+            .map(|&(ref name, _)| name.clone()));
+        known.insert(id, defined);
+    }
+    Ok(known)
+}
+
+/// Under the version 2 resolver an optional dependency is only ever enabled by
+/// a feature referring to it, so one that isn't referenced anywhere is dead
+fn check_unreferenced_optional(deps: &[::upload::CrateDependency],
+                               features: &HashMap<::upload::CrateName, Vec<::upload::Feature>>,
+                               policy: &DependencyPolicy,
+                               warnings: &mut Vec<String>)
+                               -> CargoResult<()> {
+    if policy.unreferenced_optional == Severity::Allow {
+        return Ok(())
+    }
+    // Without any features at all none of them can be reached, which is
+    // worth saying once rather than once per dependency
+    if features.is_empty() {
+        let optional = deps.iter().filter(|d| d.optional).collect::<Vec<_>>();
+        if optional.is_empty() {
+            return Ok(())
+        }
+        let names = optional.iter()
+            .map(|d| format!("`{}`", &*d.name))
+            .collect::<Vec<_>>();
+        let reason = format!("the crate defines no features, so optional dependencies \
+                              {} are unreachable", names.join(", "));
+        if policy.unreferenced_optional == Severity::Deny {
+            let dep = optional[0];
+            return Err(reject(policy, dep, &*dep.name, reason))
+        }
+        warnings.push(reason);
+        return Ok(())
+    }
+    for dep in deps.iter().filter(|d| d.optional) {
+        let name = &*dep.name;
+        let referenced = features.values().flat_map(|values| values).any(|value| {
+            let value = &**value;
+            value == format!("dep:{}", name) ||
+                value.starts_with(&format!("{}/", name)) ||
+                value.starts_with(&format!("{}?/", name))
+        });
+        if referenced {
+            continue
+        }
+        let reason = format!("optional dependency `{}` is not enabled by any \
+                              feature, so it can never be used", name);
+        if policy.unreferenced_optional == Severity::Deny {
+            return Err(reject(policy, dep, name, reason))
+        }
+        warnings.push(reason);
+    }
+    Ok(())
+}
+
+/// Rejects every dependency the allowlist doesn't cover at once, so they can
+/// all be fixed in one go
+fn check_allowlist(deps: &[::upload::CrateDependency], allowlist: &DependencyAllowlist,
+                   policy: &DependencyPolicy) -> CargoResult<()> {
+    let disallowed = deps.iter()
+        .filter(|d| !allowlist.allows(&d.name, &d.version_req))
+        .collect::<Vec<_>>();
+    if disallowed.is_empty() {
+        return Ok(());
+    }
+    let list = disallowed.iter()
+        .map(|d| format!("`{} {}`", &*d.name, d.version_req.1.trim()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let reason = format!("dependencies aren't covered by the allowlist: {}", list);
+    for dep in &disallowed {
+        policy.audit(AuditEvent {
+            dependency: dep.name.to_string(),
+            original: dep.version_req.1.clone(),
+            action: AuditAction::Rejected,
+            reason: reason.clone(),
+        });
+    }
+    Err(human(&reason))
+}
+
+/// Records that `dep` was rejected with the audit hook, and turns `reason`
+/// into the error reported to the publisher
+fn reject(policy: &DependencyPolicy, dep: &::upload::CrateDependency,
+          original: &str, reason: String) -> Box<CargoError> {
+    policy.audit(AuditEvent {
+        dependency: dep.name.to_string(),
+        original: original.to_string(),
+        action: AuditAction::Rejected,
+        reason: reason.clone(),
+    });
+    human(&reason)
+}
+
+/// Loads a single dependency by its id
+pub fn find(conn: &PgConnection, dependency_id: i32) -> CargoResult<Dependency> {
+    dependencies::table
+        .find(dependency_id)
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| human(&format_args!("no dependency with id {}", dependency_id)))
+}
+
+/// Loads all dependencies of a version, in the order they were inserted
+pub fn find_by_version_id(conn: &PgConnection, version_id: i32) -> CargoResult<Vec<Dependency>> {
+    dependencies::table
+        .filter(dependencies::version_id.eq(version_id))
+        .order(dependencies::id)
+        .load(conn)
+        .map_err(Into::into)
+}
+
+/// Returns a canonical, order independent description of a version's
+/// dependencies, with one line per dependency. Two versions have the same
+/// snapshot exactly when they declare the same dependencies.
+pub fn dependency_snapshot(conn: &PgConnection, version_id: i32) -> CargoResult<Vec<String>> {
+    Ok(snapshot_lines(&find_by_version_id(conn, version_id)?))
+}
+
+fn snapshot_lines(deps: &[Dependency]) -> Vec<String> {
+    let mut lines = deps.iter()
+        .cloned()
+        .map(|mut dep| {
+            dep.normalize();
+            format!("{} {} {} optional={} default_features={} features=[{}] target={}",
+                    dep.crate_id, dep.req, dep.kind.name(), dep.optional,
+                    dep.default_features, dep.features.join(","),
+                    dep.target.unwrap_or_default())
+        })
+        .collect::<Vec<_>>();
+    lines.sort();
+    lines
+}
+
+/// Compares two sets of dependencies, ignoring their order and which version
+/// they belong to
+pub fn dependency_sets_equal(a: &[Dependency], b: &[Dependency]) -> bool {
+    snapshot_lines(a) == snapshot_lines(b)
+}
+
+/// Whether two versions declare exactly the same dependencies, which usually
+/// means one was republished for reasons unrelated to its dependencies
+pub fn versions_have_same_dependencies(conn: &PgConnection, version_id_a: i32,
+                                       version_id_b: i32) -> CargoResult<bool> {
+    let a = find_by_version_id(conn, version_id_a)?;
+    let b = find_by_version_id(conn, version_id_b)?;
+    Ok(dependency_sets_equal(&a, &b))
+}
+
+/// Renders added and removed dependencies one per line, as `+ foo ^1.0 (normal)`
+/// and `- bar ^2.0 (dev)`. Crates missing from `names` are shown by id.
+pub fn format_dependency_diff(added: &[Dependency], removed: &[Dependency],
+                              names: &HashMap<i32, String>) -> String {
+    let line = |sign: &str, dep: &Dependency| {
+        let name = names.get(&dep.crate_id).cloned()
+            .unwrap_or_else(|| format!("#{}", dep.crate_id));
+        format!("{} {}", sign, dep.summary(&name))
+    };
+    added.iter().map(|d| line("+", d))
+        .chain(removed.iter().map(|d| line("-", d)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Reorders `deps` so crates come in the order they're listed in
+/// `name_order`, followed by the unlisted ones sorted by name. Dependencies
+/// on the same crate keep their relative order.
+pub fn sort_dependencies_by_names(deps: &mut Vec<Dependency>, name_order: &[String],
+                                  names: &HashMap<i32, String>) {
+    let position = name_order.iter()
+        .enumerate()
+        .map(|(i, name)| (&**name, i))
+        .collect::<HashMap<_, _>>();
+    let key = |dep: &Dependency| {
+        let name = names.get(&dep.crate_id).map(|n| &**n).unwrap_or("");
+        match position.get(name) {
+            Some(&i) => (i, ""),
+            None => (name_order.len(), name),
+        }
+    };
+    deps.sort_by(|a, b| key(a).cmp(&key(b)));
+}
+
+/// Returns an HTTP entity tag for a version's dependencies, which only
+/// changes when the set of dependencies does
+pub fn dependencies_etag(conn: &PgConnection, version_id: i32) -> CargoResult<String> {
+    use openssl::hash::{Hasher, MessageDigest};
+    use rustc_serialize::hex::ToHex;
+
+    let mut hasher = Hasher::new(MessageDigest::sha256())?;
+    for line in dependency_snapshot(conn, version_id)? {
+        hasher.update(line.as_bytes())?;
+        hasher.update(b"\n")?;
+    }
+    Ok(format!("\"{}\"", hasher.finish()?.to_hex()))
+}
+
+/// Returns the dependencies of a version which are only used on platforms
+/// outside of `cfg::COMMON_PLATFORMS`
+pub fn niche_platform_dependencies(conn: &PgConnection, version_id: i32)
+                                   -> CargoResult<Vec<Dependency>> {
+    let deps = find_by_version_id(conn, version_id)?;
+    Ok(deps.into_iter().filter(|dep| {
+        dep.target.as_ref()
+            .and_then(|t| cfg::Platform::parse(t).ok())
+            .map_or(false, |t| cfg::target_platforms(&t).is_empty())
+    }).collect())
+}
+
+/// Returns the names of crates which a version depends on through several
+/// target specific rows that are otherwise identical and that together cover
+/// every platform in `cfg::COMMON_PLATFORMS`, such as one row for
+/// `cfg(unix)` and another for `cfg(windows)`. These could be replaced by a
+/// single unconditional dependency.
+pub fn mergeable_target_dependencies(conn: &PgConnection, version_id: i32)
+                                     -> CargoResult<Vec<String>> {
+    let mut groups = HashMap::new();
+    for mut dep in find_by_version_id(conn, version_id)? {
+        dep.normalize();
+        let target = match dep.target.as_ref().and_then(|t| cfg::Platform::parse(t).ok()) {
+            Some(target) => target,
+            None => continue,
+        };
+        let key = (dep.crate_id, dep.kind, dep.req.to_string(), dep.features,
+                   dep.optional, dep.default_features);
+        groups.entry(key).or_insert_with(Vec::new).push(target);
+    }
+
+    let crate_ids = groups.iter()
+        .filter(|&(_, targets)| {
+            targets.len() > 1 && cfg::COMMON_PLATFORMS.iter().all(|p| {
+                targets.iter().any(|t| cfg::target_matches(t, p))
+            })
+        })
+        .map(|(key, _)| key.0)
+        .collect::<Vec<_>>();
+    let mut names = resolve_names_and_downloads(conn, &crate_ids)?
+        .into_iter()
+        .map(|(_, (name, _))| name)
+        .collect::<Vec<_>>();
+    names.sort();
+    Ok(names)
+}
+
+/// The distinct crates a version depends on, sorted by id
+pub fn dependency_crate_ids(conn: &PgConnection, version_id: i32) -> CargoResult<Vec<i32>> {
+    dependencies::table
+        .filter(dependencies::version_id.eq(version_id))
+        .select(dependencies::crate_id)
+        .distinct()
+        .order(dependencies::crate_id)
+        .load(conn)
+        .map_err(Into::into)
+}
+
+/// The Jaccard index of the crates two versions depend on, from `0.0` for
+/// nothing in common to `1.0` for the same crates. Two versions without any
+/// dependencies have nothing to compare, so they score `0.0`.
+pub fn dependency_jaccard(conn: &PgConnection, version_id_a: i32, version_id_b: i32)
+                          -> CargoResult<f64> {
+    let a = dependency_crate_ids(conn, version_id_a)?.into_iter().collect::<HashSet<_>>();
+    let b = dependency_crate_ids(conn, version_id_b)?.into_iter().collect::<HashSet<_>>();
+    let union = a.union(&b).count();
+    if union == 0 {
+        return Ok(0.0)
+    }
+    Ok(a.intersection(&b).count() as f64 / union as f64)
+}
+
+/// Compares the crates depended upon by two versions, usually consecutive
+/// versions of the same crate
+pub fn dependency_diff(conn: &PgConnection, old_version_id: i32, new_version_id: i32)
+                       -> CargoResult<DependencyDiff> {
+    let old = dependency_crate_ids(conn, old_version_id)?;
+    let new = dependency_crate_ids(conn, new_version_id)?;
+    Ok(DependencyDiff {
+        added: new.iter().filter(|id| !old.contains(id)).cloned().collect(),
+        removed: old.iter().filter(|id| !new.contains(id)).cloned().collect(),
+    })
+}
+
+/// The names of the crates which the latest version of a crate depends on,
+/// but the version before it didn't
+pub fn newly_added_dependencies(conn: &PgConnection, crate_id: i32)
+                                -> CargoResult<Vec<String>> {
+    let mut versions = versions::table
+        .filter(versions::crate_id.eq(crate_id))
+        .select((versions::id, versions::num))
+        .load::<(i32, String)>(conn)?
+        .into_iter()
+        .map(|(id, num)| (semver::Version::parse(&num).unwrap(), id))
+        .collect::<Vec<_>>();
+    versions.sort();
+
+    let added = match (versions.pop(), versions.pop()) {
+        (Some((_, latest)), Some((_, previous))) => {
+            dependency_diff(conn, previous, latest)?.added
+        }
+        (Some((_, latest)), None) => dependency_crate_ids(conn, latest)?,
+        _ => Vec::new(),
+    };
+    let mut names = resolve_names_and_downloads(conn, &added)?
+        .into_iter()
+        .map(|(_, (name, _))| name)
+        .collect::<Vec<_>>();
+    names.sort();
+    Ok(names)
+}
+
+/// Averages `req::looseness` over the normal and build dependencies of a
+/// version. Dev dependencies don't affect users of the crate, so they're
+/// ignored, and a version with nothing else to go on scores `1.0`.
+pub fn dependency_looseness(conn: &PgConnection, version_id: i32) -> CargoResult<f64> {
+    let scores = find_by_version_id(conn, version_id)?
+        .iter()
+        .filter(|dep| dep.kind != Kind::Dev)
+        .map(|dep| req::looseness(&dep.req))
+        .collect::<Vec<_>>();
+    if scores.is_empty() {
+        return Ok(1.0)
+    }
+    Ok(scores.iter().sum::<f64>() / scores.len() as f64)
+}
+
+/// Suggests dependencies which could be made optional: normal, unconditional
+/// dependencies whose features are only ever touched by a single feature of
+/// the crate, which hints that they're only needed for that feature. This is
+/// only a heuristic, since it can't tell whether the code uses the dependency
+/// elsewhere.
+pub fn optional_candidate_dependencies(conn: &PgConnection, version_id: i32,
+                                       features: &HashMap<String, Vec<String>>)
+                                       -> CargoResult<Vec<String>> {
+    let deps = find_by_version_id(conn, version_id)?
+        .into_iter()
+        .filter(|d| d.kind == Kind::Normal && !d.optional && d.target.is_none())
+        .collect::<Vec<_>>();
+    let ids = deps.iter().map(|d| d.crate_id).collect::<Vec<_>>();
+    let names = resolve_names_and_downloads(conn, &ids)?;
+
+    let mut candidates = deps.iter()
+        .filter_map(|d| names.get(&d.crate_id).map(|&(ref name, _)| name))
+        .filter(|name| {
+            let prefix = format!("{}/", name);
+            let referencing = features.values()
+                .filter(|values| values.iter().any(|v| v.starts_with(&prefix)))
+                .count();
+            referencing == 1
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    candidates.sort();
+    candidates.dedup();
+    Ok(candidates)
+}
+
+/// The names of the dependencies of a version which are built when the given
+/// features are enabled. Non-optional dependencies are always active; an
+/// optional one is activated by a feature naming it (`foo` or `dep:foo`) or enabling
+/// one of its features (`foo/bar`, but not the weak `foo?/bar`), directly or
+/// through other features.
+pub fn active_dependencies_under_features(conn: &PgConnection, version_id: i32,
+                                          features: &HashMap<String, Vec<String>>,
+                                          enabled: &[&str])
+                                          -> CargoResult<Vec<String>> {
+    let deps = find_by_version_id(conn, version_id)?;
+    let ids = deps.iter().map(|d| d.crate_id).collect::<Vec<_>>();
+    let names = resolve_names_and_downloads(conn, &ids)?;
+    let optional = deps.iter()
+        .filter(|d| d.optional)
+        .filter_map(|d| names.get(&d.crate_id).map(|&(ref name, _)| &**name))
+        .collect::<HashSet<_>>();
+    let activated = activated_dependencies(features, &optional, enabled);
+
+    let mut active = deps.iter()
+        .filter_map(|d| names.get(&d.crate_id).map(|&(ref name, _)| (d, name)))
+        .filter(|&(d, name)| !d.optional || activated.contains(name))
+        .map(|(_, name)| name.clone())
+        .collect::<Vec<_>>();
+    active.sort();
+    active.dedup();
+    Ok(active)
+}
+
+/// Each feature of a version which activates optional dependencies, with the
+/// names of those dependencies, so dependencies which are always enabled
+/// together can be treated as a group. Both lists are sorted by name.
+pub fn dependency_feature_groups(conn: &PgConnection, version_id: i32,
+                                 features: &HashMap<String, Vec<String>>)
+                                 -> CargoResult<Vec<(String, Vec<String>)>> {
+    let deps = find_by_version_id(conn, version_id)?
+        .into_iter()
+        .filter(|d| d.optional)
+        .collect::<Vec<_>>();
+    let ids = deps.iter().map(|d| d.crate_id).collect::<Vec<_>>();
+    let names = resolve_names_and_downloads(conn, &ids)?;
+    let optional = names.values().map(|&(ref name, _)| &**name).collect::<HashSet<_>>();
+
+    let mut groups = features.keys()
+        .filter_map(|feature| {
+            let mut enabled = activated_dependencies(features, &optional, &[&**feature])
+                .into_iter()
+                .filter(|name| optional.contains(&**name))
+                .collect::<Vec<_>>();
+            if enabled.is_empty() {
+                return None;
+            }
+            enabled.sort();
+            Some((feature.clone(), enabled))
+        })
+        .collect::<Vec<_>>();
+    groups.sort();
+    Ok(groups)
+}
+
+/// The dependencies which enabling `enabled` activates, following features
+/// through the feature map. Only names in `optional` are activated by a bare
+/// feature name, since anything else is just a feature.
+fn activated_dependencies(features: &HashMap<String, Vec<String>>,
+                          optional: &HashSet<&str>,
+                          enabled: &[&str]) -> HashSet<String> {
+    let mut activated = HashSet::new();
+    let mut visited = HashSet::new();
+    let mut pending = enabled.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+    while let Some(feature) = pending.pop() {
+        if !visited.insert(feature.clone()) {
+            continue;
+        }
+        if feature.starts_with("dep:") {
+            activated.insert(feature[4..].to_string());
+        } else if let Some(slash) = feature.find('/') {
+            if !feature[..slash].ends_with('?') {
+                activated.insert(feature[..slash].to_string());
+            }
+        } else {
+            if optional.contains(&*feature) {
+                activated.insert(feature.clone());
+            }
+            if let Some(values) = features.get(&feature) {
+                pending.extend(values.iter().cloned());
+            }
+        }
+    }
+    activated
+}
+
+/// The names of the dependencies of a version which a build with the default
+/// features doesn't need, because only non-default features activate them
+pub fn non_default_dependencies(conn: &PgConnection, version_id: i32,
+                                features: &HashMap<String, Vec<String>>)
+                                -> CargoResult<Vec<String>> {
+    let defaults = active_dependencies_under_features(conn, version_id, features,
+                                                      &["default"])?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let all = find_by_version_id(conn, version_id)?
+        .iter()
+        .map(|d| d.crate_id)
+        .collect::<Vec<_>>();
+    let mut unused = resolve_names_and_downloads(conn, &all)?
+        .into_iter()
+        .map(|(_, (name, _))| name)
+        .filter(|name| !defaults.contains(name))
+        .collect::<Vec<_>>();
+    unused.sort();
+    Ok(unused)
+}
+
+/// The non-yanked versions of each of the given crates, oldest first
+fn published_versions(conn: &PgConnection, crate_ids: &[i32])
+                      -> CargoResult<HashMap<i32, Vec<semver::Version>>> {
+    use diesel::expression::dsl::any;
+
+    let rows = versions::table
+        .filter(versions::crate_id.eq(any(crate_ids)))
+        .filter(versions::yanked.eq(false))
+        .select((versions::crate_id, versions::num))
+        .load::<(i32, String)>(conn)?;
+    let mut versions = HashMap::new();
+    for (crate_id, num) in rows {
+        versions.entry(crate_id).or_insert_with(Vec::new)
+            .push(semver::Version::parse(&num).unwrap());
+    }
+    for nums in versions.values_mut() {
+        nums.sort();
+    }
+    Ok(versions)
+}
+
+/// The newest of `versions` matching `req`
+fn matching_latest<'a>(versions: &'a [semver::Version], req: &semver::VersionReq)
+                       -> Option<&'a semver::Version> {
+    versions.iter().filter(|v| req.matches(v)).max()
+}
+
+/// Resolves each dependency of a version to the newest version it allows,
+/// one level deep
+pub fn resolved_dependency_edges(conn: &PgConnection, version_id: i32)
+                                 -> CargoResult<Vec<ResolvedEdge>> {
+    let deps = find_by_version_id(conn, version_id)?;
+    let ids = deps.iter().map(|d| d.crate_id).collect::<Vec<_>>();
+    let names = resolve_names_and_downloads(conn, &ids)?;
+    let versions = published_versions(conn, &ids)?;
+
+    Ok(deps.iter().map(|dep| {
+        ResolvedEdge {
+            name: names.get(&dep.crate_id).map(|n| n.0.clone()).unwrap_or_default(),
+            req: dep.index_req_string(),
+            kind: dep.kind,
+            resolved_version: versions.get(&dep.crate_id)
+                .and_then(|versions| matching_latest(versions, &dep.req))
+                .cloned(),
+        }
+    }).collect())
+}
+
+/// Lists the dependencies of a version whose requirement doesn't reach the
+/// latest major version of the crate depended upon, as `(name, req, latest
+/// major)`.
+pub fn lagging_dependencies(conn: &PgConnection, version_id: i32)
+                            -> CargoResult<Vec<(String, String, String)>> {
+    let deps = find_by_version_id(conn, version_id)?;
+    let ids = deps.iter().map(|d| d.crate_id).collect::<Vec<_>>();
+    let names = resolve_names_and_downloads(conn, &ids)?;
+    let versions = published_versions(conn, &ids)?;
+
+    let mut lagging = Vec::new();
+    for dep in deps {
+        let latest = match versions.get(&dep.crate_id).and_then(|v| v.last()) {
+            Some(latest) => latest,
+            None => continue,
+        };
+        let upper = match req::req_upper_bound(&dep.req) {
+            Some(upper) => upper,
+            None => continue,
+        };
+        // Nothing from the latest major's first release onwards is allowed
+        if (upper.major, upper.minor, upper.patch) <= (latest.major, 0, 0) {
+            let name = names.get(&dep.crate_id).map(|n| n.0.clone()).unwrap_or_default();
+            lagging.push((name, dep.req.to_string(), latest.major.to_string()));
+        }
+    }
+    lagging.sort();
+    Ok(lagging)
+}
+
+/// Resolves each dependency of a version to the oldest published version
+/// matching it, like `cargo -Z minimal-versions` would. Dependencies which
+/// nothing matches are left out.
+pub fn minimal_version_resolution(conn: &PgConnection, version_id: i32)
+                                  -> CargoResult<Vec<(String, semver::Version)>> {
+    let deps = find_by_version_id(conn, version_id)?;
+    let ids = deps.iter().map(|d| d.crate_id).collect::<Vec<_>>();
+    let names = resolve_names_and_downloads(conn, &ids)?;
+    let versions = published_versions(conn, &ids)?;
+
+    let mut resolution = deps.iter().filter_map(|dep| {
+        let lower = req::req_lower_bound(&dep.req);
+        let minimal = versions.get(&dep.crate_id).and_then(|versions| {
+            versions.iter()
+                .skip_while(|v| lower.as_ref().map_or(false, |lower| *v < lower))
+                .find(|v| dep.req.matches(v))
+        });
+        match (names.get(&dep.crate_id), minimal) {
+            (Some(&(ref name, _)), Some(minimal)) => Some((name.clone(), minimal.clone())),
+            _ => None,
+        }
+    }).collect::<Vec<_>>();
+    resolution.sort();
+    Ok(resolution)
+}
+
+/// The names of the crates a version depends on as both a normal and a build
+/// dependency, which may or may not be intentional
+pub fn normal_build_overlaps(conn: &PgConnection, version_id: i32)
+                             -> CargoResult<Vec<String>> {
+    let deps = find_by_version_id(conn, version_id)?;
+    let of_kind = |kind| {
+        deps.iter().filter(|d| d.kind == kind).map(|d| d.crate_id).collect::<HashSet<_>>()
+    };
+    let overlap = of_kind(Kind::Normal).intersection(&of_kind(Kind::Build))
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut names = resolve_names_and_downloads(conn, &overlap)?
+        .into_iter()
+        .map(|(_, (name, _))| name)
+        .collect::<Vec<_>>();
+    names.sort();
+    Ok(names)
+}
+
+/// The names of the normal and build dependencies of a version which are only
+/// used when building tests or with debug assertions, and would be better off
+/// as dev dependencies
+pub fn effectively_dev_dependencies(conn: &PgConnection, version_id: i32)
+                                    -> CargoResult<Vec<String>> {
+    let ids = find_by_version_id(conn, version_id)?
+        .into_iter()
+        .filter(|dep| dep.kind != Kind::Dev)
+        .filter(|dep| {
+            dep.target.as_ref()
+                .and_then(|t| cfg::Platform::parse(t).ok())
+                .map_or(false, |t| cfg::only_in_test_or_debug(&t))
+        })
+        .map(|dep| dep.crate_id)
+        .collect::<Vec<_>>();
+    let mut names = resolve_names_and_downloads(conn, &ids)?
+        .into_iter()
+        .map(|(_, (name, _))| name)
+        .collect::<Vec<_>>();
+    names.sort();
+    Ok(names)
+}
+
+/// The features a version enables on `crate_name`, for each target it
+/// depends on that crate under. Untargeted dependencies are under `None`, and
+/// each list is sorted and deduplicated across kinds.
+pub fn features_by_target(conn: &PgConnection, version_id: i32, crate_name: &str)
+                          -> CargoResult<HashMap<Option<String>, Vec<String>>> {
+    let krate = Crate::by_name(crate_name).first::<Crate>(conn)?;
+    let deps = dependencies::table
+        .filter(dependencies::version_id.eq(version_id))
+        .filter(dependencies::crate_id.eq(krate.id))
+        .load::<Dependency>(conn)?;
+
+    let mut by_target = HashMap::new();
+    for dep in deps {
+        let target = dep.target.as_ref().map(|t| normalize_target(t));
+        by_target.entry(target).or_insert_with(Vec::new).extend(dep.features);
+    }
+    for features in by_target.values_mut() {
+        features.sort();
+        features.dedup();
+    }
+    Ok(by_target)
+}
+
+/// Loads the dependencies of the given versions as a `DependencyColumns`
+/// batch, ordered by version and then by dependency id
+pub fn dependency_columns(conn: &PgConnection, version_ids: &[i32])
+                          -> CargoResult<DependencyColumns> {
+    use diesel::expression::dsl::any;
+
+    let deps = dependencies::table
+        .filter(dependencies::version_id.eq(any(version_ids)))
+        .order((dependencies::version_id, dependencies::id))
+        .load::<Dependency>(conn)?;
+    let ids = deps.iter().map(|d| d.crate_id).collect::<Vec<_>>();
+    let names = resolve_names_and_downloads(conn, &ids)?;
+
+    let mut columns = DependencyColumns::default();
+    for dep in deps {
+        let name = match names.get(&dep.crate_id) {
+            Some(&(ref name, _)) => name.clone(),
+            None => return Err(internal(&format_args!("no crate with id {}", dep.crate_id))),
+        };
+        columns.version_id.push(dep.version_id);
+        columns.crate_name.push(name);
+        columns.req.push(dep.published_req());
+        columns.kind_code.push(dep.kind.stable_code());
+        columns.optional.push(dep.optional);
+        columns.default_features.push(dep.default_features);
+        columns.target.push(dep.target);
+    }
+    Ok(columns)
+}
+
+/// How many bytes each dependency of a version takes up in the version's
+/// index line, largest first. Long feature lists are usually what makes a
+/// line slow for Cargo to fetch.
+pub fn index_size_contributors(conn: &PgConnection, version_id: i32)
+                               -> CargoResult<Vec<(String, usize)>> {
+    let deps = find_by_version_id(conn, version_id)?;
+    let ids = deps.iter().map(|d| d.crate_id).collect::<Vec<_>>();
+    let names = resolve_names_and_downloads(conn, &ids)?;
+
+    let mut sizes = deps.iter()
+        .filter_map(|d| {
+            names.get(&d.crate_id)
+                .map(|&(ref name, _)| (name.clone(), d.to_index_value(name).len()))
+        })
+        .collect::<Vec<_>>();
+    sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(sizes)
+}
+
+/// Summarizes the dependencies of a version into a `DependencySurface`
+pub fn dependency_surface(conn: &PgConnection, version_id: i32)
+                          -> CargoResult<DependencySurface> {
+    let deps = find_by_version_id(conn, version_id)?;
+    let mut surface = DependencySurface::default();
+    let mut crates = HashSet::new();
+    for dep in &deps {
+        match dep.kind {
+            Kind::Normal => surface.normal += 1,
+            Kind::Build => surface.build += 1,
+            Kind::Dev => surface.dev += 1,
+        }
+        if dep.optional {
+            surface.optional += 1;
+        }
+        if dep.target.is_some() {
+            surface.platform_specific += 1;
+        }
+        crates.insert(dep.crate_id);
+    }
+    surface.distinct_crates = crates.len();
+    Ok(surface)
+}
+
+/// Lists the ids of the dependencies of `crate_id`'s versions whose
+/// requirement is stored in a legacy form, as a report of which rows still
+/// need migrating
+pub fn shimmed_dependency_ids(conn: &PgConnection, crate_id: i32) -> CargoResult<Vec<i32>> {
+    let version_ids = versions::table
+        .filter(versions::crate_id.eq(crate_id))
+        .select(versions::id);
+    let rows = dependencies::table
+        .filter(dependencies::version_id.eq_any(version_ids))
+        .order(dependencies::id)
+        .select((dependencies::id, dependencies::req))
+        .load::<(i32, String)>(conn)?;
+    Ok(rows.into_iter()
+        .filter(|&(_, ref req)| {
+            req::parse_compat(req).map(|(_, shimmed)| shimmed).unwrap_or(false)
+        })
+        .map(|(id, _)| id)
+        .collect())
+}
+
+/// Rewrites the requirements of every dependency of `crate_id`'s versions in
+/// the form the current `semver` gives them, for after an upgrade changes how
+/// requirements are serialized. Requirements stored verbatim under
+/// `preserve_raw_req` and ones which no longer parse at all are left alone.
+/// Returns how many rows were updated.
+pub fn recanonicalize_reqs(conn: &PgConnection, crate_id: i32) -> CargoResult<u64> {
+    use diesel::expression::dsl::any;
+    use diesel::update;
+
+    conn.transaction(|| {
+        let version_ids = versions::table
+            .filter(versions::crate_id.eq(crate_id))
+            .select(versions::id)
+            .load::<i32>(conn)?;
+        let rows = dependencies::table
+            .filter(dependencies::version_id.eq(any(version_ids)))
+            .filter(dependencies::req_is_raw.eq(false))
+            .select((dependencies::id, dependencies::req))
+            .load::<(i32, String)>(conn)?;
+
+        let mut updated = 0;
+        for (id, stored) in rows {
+            let canonical = match req::parse_compat(&stored) {
+                Ok((req, _)) => req.to_string(),
+                Err(_) => continue,
+            };
+            if canonical != stored {
+                updated += update(dependencies::table.find(id))
+                    .set(dependencies::req.eq(canonical))
+                    .execute(conn)? as u64;
+            }
+        }
+        Ok(updated)
+    })
+}
+
+/// Looks up the name and download count of each of the given crates in a
+/// single query
+pub fn resolve_names_and_downloads(conn: &PgConnection, crate_ids: &[i32])
+                                   -> CargoResult<HashMap<i32, (String, i64)>> {
+    use diesel::expression::dsl::any;
+
+    let rows = crates::table
+        .filter(crates::id.eq(any(crate_ids)))
+        .select((crates::id, crates::name, crates::downloads))
+        .load::<(i32, String, i32)>(conn)?;
+    Ok(rows.into_iter()
+        .map(|(id, name, downloads)| (id, (name, downloads as i64)))
+        .collect())
+}
+
+/// Counts the distinct crates with a non-yanked version depending on each of
+/// the given crates. Crates nothing depends on are left out.
+pub fn count_reverse_dependencies(conn: &GenericConnection, crate_ids: &[i32])
+                                  -> CargoResult<HashMap<i32, i64>> {
+    let stmt = conn.prepare("SELECT dependencies.crate_id,
+                                    COUNT(DISTINCT versions.crate_id) AS dependents
+                               FROM dependencies
+                              INNER JOIN versions ON versions.id = dependencies.version_id
+                              WHERE dependencies.crate_id = ANY($1)
+                                AND NOT versions.yanked
+                              GROUP BY dependencies.crate_id")?;
+    let rows = stmt.query(&[&crate_ids])?;
+    Ok(rows.iter().map(|row| (row.get("crate_id"), row.get("dependents"))).collect())
+}
+
+/// Each distinct requirement other versions place on a crate, with how many
+/// dependency rows use it, most used first
+pub fn distinct_requirements(conn: &GenericConnection, crate_id: i32)
+                             -> CargoResult<Vec<(String, i64)>> {
+    let stmt = conn.prepare("SELECT req, COUNT(*) AS count
+                               FROM dependencies
+                              WHERE crate_id = $1
+                              GROUP BY req
+                              ORDER BY count DESC, req")?;
+    let rows = stmt.query(&[&crate_id])?;
+    Ok(rows.iter().map(|row| (row.get("req"), row.get("count"))).collect())
+}
+
+/// Counts the crates whose latest version depends on `crate_id` which would
+/// still resolve if `version` were yanked, and those which wouldn't, as
+/// `(surviving, broken)`. Like the reverse dependencies API, only each
+/// dependent crate's latest non-yanked version is considered. Crates with a
+/// requirement nothing matches even before the yank aren't counted at all,
+/// since the yank doesn't change anything for them.
+pub fn reverse_dependents_surviving_yank(conn: &PgConnection, crate_id: i32,
+                                         version: &semver::Version)
+                                         -> CargoResult<(i64, i64)> {
+    use diesel::expression::dsl::any;
+
+    let published = published_versions(conn, &[crate_id])?
+        .remove(&crate_id)
+        .unwrap_or_default();
+    let deps = dependencies::table
+        .filter(dependencies::crate_id.eq(crate_id))
+        .select((dependencies::version_id, dependencies::req))
+        .load::<(i32, String)>(conn)?;
+    let version_ids = deps.iter().map(|d| d.0).collect::<Vec<_>>();
+    let dependent_crates = versions::table
+        .filter(versions::id.eq(any(version_ids)))
+        .select(versions::crate_id)
+        .load::<i32>(conn)?;
+    let latest = latest_versions(conn, &dependent_crates)?;
+
+    // Whether each dependent crate still resolves after the yank
+    let mut survives = HashMap::new();
+    let mut already_broken = HashSet::new();
+    for (version_id, req) in deps {
+        let dependent = match latest.get(&version_id) {
+            Some(&dependent) => dependent,
+            None => continue,
+        };
+        let req = match req::parse_compat(&req) {
+            Ok((req, _)) => req,
+            Err(_) => continue,
+        };
+        if !published.iter().any(|v| req.matches(v)) {
+            already_broken.insert(dependent);
+            continue;
+        }
+        let matched = published.iter().any(|v| v != version && req.matches(v));
+        *survives.entry(dependent).or_insert(true) &= matched;
+    }
+    for dependent in &already_broken {
+        survives.remove(dependent);
+    }
+    let surviving = survives.values().filter(|&&s| s).count() as i64;
+    Ok((surviving, survives.len() as i64 - surviving))
+}
+
+/// The id of the latest non-yanked version of each of the given crates,
+/// mapped to the crate it belongs to. Pre-releases only count as latest when
+/// a crate has nothing else, matching `krate_reverse_dependencies.sql`.
+fn latest_versions(conn: &PgConnection, crate_ids: &[i32]) -> CargoResult<HashMap<i32, i32>> {
+    use diesel::expression::dsl::any;
+
+    let rows = versions::table
+        .filter(versions::crate_id.eq(any(crate_ids)))
+        .filter(versions::yanked.eq(false))
+        .select((versions::id, versions::crate_id, versions::num))
+        .load::<(i32, i32, String)>(conn)?;
+    let mut latest = HashMap::<i32, (bool, semver::Version, i32)>::new();
+    for (id, crate_id, num) in rows {
+        let num = semver::Version::parse(&num).unwrap();
+        let key = (!num.is_prerelease(), num, id);
+        let newer = latest.get(&crate_id).map_or(true, |current| key > *current);
+        if newer {
+            latest.insert(crate_id, key);
+        }
+    }
+    Ok(latest.into_iter().map(|(crate_id, (_, _, id))| (id, crate_id)).collect())
+}
+
+/// Lists the crates a version depends on along with how many crates depend
+/// on each of them, most depended upon first
+pub fn dependencies_by_weight(conn: &GenericConnection, version_id: i32)
+                              -> CargoResult<Vec<(String, i64)>> {
+    let stmt = conn.prepare("SELECT DISTINCT crates.id, crates.name
+                               FROM dependencies
+                              INNER JOIN crates ON crates.id = dependencies.crate_id
+                              WHERE dependencies.version_id = $1")?;
+    let names = stmt.query(&[&version_id])?.iter()
+        .map(|row| (row.get("id"), row.get("name")))
+        .collect::<Vec<(i32, String)>>();
+    let ids = names.iter().map(|&(id, _)| id).collect::<Vec<_>>();
+    let counts = count_reverse_dependencies(conn, &ids)?;
+
+    let mut weights = names.into_iter()
+        .map(|(id, name)| (name, counts.get(&id).cloned().unwrap_or(0)))
+        .collect::<Vec<_>>();
+    weights.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(weights)
+}
+
+/// The crates most often depended on by the same versions as `crate_id`, with
+/// the number of versions depending on both, most frequent first
+pub fn frequent_codependencies(conn: &GenericConnection, crate_id: i32, limit: i64)
+                               -> CargoResult<Vec<(String, i64)>> {
+    // A crate depended on more than once by the same version, say as both a
+    // normal and a dev dependency, still only co-occurs once
+    let stmt = conn.prepare("SELECT crates.name, COUNT(DISTINCT d1.version_id) AS count
+                               FROM dependencies d1
+                              INNER JOIN dependencies d2
+                                 ON d2.version_id = d1.version_id
+                                AND d2.crate_id <> d1.crate_id
+                              INNER JOIN crates ON crates.id = d2.crate_id
+                              WHERE d1.crate_id = $1
+                              GROUP BY d2.crate_id, crates.name
+                              ORDER BY count DESC, crates.name
+                              LIMIT $2")?;
+    let rows = stmt.query(&[&crate_id, &limit])?;
+    Ok(rows.iter().map(|row| (row.get("name"), row.get("count"))).collect())
+}
+
+/// Estimates how central a crate is to the ecosystem.
+///
+/// Every crate with a non-yanked version depending on `crate_id` contributes
+/// one point, plus one point for each crate which in turn depends on it. This
+/// only looks two levels deep, so it's a rough measure rather than a full
+/// transitive count.
+pub fn weighted_reverse_impact(conn: &GenericConnection, crate_id: i32) -> CargoResult<i64> {
+    let stmt = conn.prepare(include_str!("weighted_reverse_impact.sql"))?;
+    let rows = stmt.query(&[&crate_id])?;
+    Ok(rows.get(0).get("impact"))
+}
+
+/// Recomputes the cached download counts of every crate with a non-yanked
+/// version depending on `crate_id` from the counts of their versions. Reverse
+/// dependencies are sorted and reported by `crates.reverse_dependency_downloads`
+/// once it's been filled in, falling back to `crates.downloads` before then.
+/// Returns how many crates had their cache updated.
+pub fn refresh_reverse_download_cache(conn: &GenericConnection, crate_id: i32)
+                                      -> CargoResult<u64> {
+    let stmt = conn.prepare(include_str!("refresh_reverse_download_cache.sql"))?;
+    Ok(stmt.execute(&[&crate_id])?)
+}
+
+/// Exports every crate whose latest version depends on `crate_id` as CSV,
+/// with the same rows as the reverse dependencies API
+pub fn reverse_dependencies_csv(conn: &GenericConnection, crate_id: i32)
+                                -> CargoResult<String> {
+    let stmt = conn.prepare(include_str!("../krate_reverse_dependencies.sql"))?;
+    let rows = stmt.query(&[&crate_id, &0i64, &i64::max_value()])?;
+
+    let mut csv = String::from("dependent_crate,req,kind,optional,target,downloads\n");
+    for row in rows.iter() {
+        let rev_dep: ReverseDependency = Model::from_row(&row);
+        let dep = rev_dep.dependency;
+        let fields = [
+            rev_dep.crate_name,
+            dep.published_req(),
+            dep.kind.name().to_string(),
+            dep.optional.to_string(),
+            dep.target.unwrap_or_default(),
+            rev_dep.crate_downloads.to_string(),
+        ];
+        let fields = fields.iter().map(|f| csv_field(f)).collect::<Vec<_>>();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    Ok(csv)
+}
+
+/// The reverse dependencies of `crate_id`, like `Crate::reverse_dependencies`
+/// but unpaginated and limited to dependent crates `user_id` owns
+pub fn reverse_dependencies_owned_by(conn: &GenericConnection, crate_id: i32, user_id: i32)
+                                     -> CargoResult<Vec<ReverseDependency>> {
+    use owner::OwnerKind;
+
+    let stmt = conn.prepare("SELECT crates.name FROM crates
+                             INNER JOIN crate_owners
+                                ON crate_owners.crate_id = crates.id
+                             WHERE crate_owners.owner_id = $1
+                               AND crate_owners.owner_kind = $2
+                               AND NOT crate_owners.deleted")?;
+    let owned = stmt.query(&[&user_id, &(OwnerKind::User as i32)])?
+        .iter()
+        .map(|row| row.get::<_, String>("name"))
+        .collect::<HashSet<_>>();
+
+    let stmt = conn.prepare(include_str!("../krate_reverse_dependencies.sql"))?;
+    let rows = stmt.query(&[&crate_id, &0i64, &i64::max_value()])?;
+    Ok(rows.iter()
+        .map(|row| Model::from_row(&row))
+        .filter(|rev_dep: &ReverseDependency| owned.contains(&rev_dep.crate_name))
+        .collect())
+}
+
+/// Quotes a CSV field if it contains anything which would otherwise be read
+/// as the end of the field
+fn csv_field(field: &str) -> String {
+    if field.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl Queryable<dependencies::SqlType, Pg> for Dependency {
+    type Row = (i32, i32, i32, String, bool, bool, Vec<String>, Option<String>,
+                i32, bool);
+
+    fn build(row: Self::Row) -> Self {
+        let (req, req_was_shimmed) = req::parse_compat(&row.3).unwrap();
+        Dependency {
+            id: row.0,
+            version_id: row.1,
+            crate_id: row.2,
+            req: req,
+            optional: row.4,
+            default_features: row.5,
+            features: row.6,
+            target: row.7,
+            kind: match row.8 {
+                0 => Kind::Normal,
+                1 => Kind::Build,
+                2 => Kind::Dev,
+                n => panic!("unknown kind: {}", n),
+            },
+            req_was_shimmed: req_was_shimmed,
+            raw_req: row.3,
+            req_is_raw: row.9,
+        }
+    }
+}
+
+impl Model for Dependency {
+    fn from_row(row: &Row) -> Dependency {
+        let raw_req: String = row.get("req");
+        let (req, req_was_shimmed) = req::parse_compat(&raw_req).unwrap();
+        Dependency {
+            id: row.get("id"),
+            version_id: row.get("version_id"),
+            crate_id: row.get("crate_id"),
+            req: req,
+            optional: row.get("optional"),
+            default_features: row.get("default_features"),
+            features: row.get("features"),
+            target: row.get("target"),
+            kind: match row.get("kind") {
+                0 => Kind::Normal,
+                1 => Kind::Build,
+                2 => Kind::Dev,
+                n => panic!("unknown kind: {}", n),
+            },
+            req_was_shimmed: req_was_shimmed,
+            raw_req: raw_req,
+            req_is_raw: row.get("req_is_raw"),
+        }
+    }
+
+    fn table_name(_: Option<Dependency>) -> &'static str { "dependencies" }
+}
+
+impl Model for ReverseDependency {
+    fn from_row(row: &Row) -> Self {
+        ReverseDependency {
+            dependency: Model::from_row(row),
+            crate_name: row.get("crate_name"),
+            crate_downloads: row.get("crate_downloads"),
+        }
+    }
+
+    fn table_name(_: Option<Self>) -> &'static str { panic!("no table") }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::sync::{Arc, Mutex};
+    use dotenv::dotenv;
+
+    use krate::NewCrate;
+    use upload::{CrateDependency, CrateName, CrateVersionReq, Feature};
+    use user::{NewUser, User};
+    use version::{NewVersion, Version};
+
+    fn connection() -> PgConnection {
+        let _ = dotenv();
+        let database_url = env::var("TEST_DATABASE_URL")
+            .expect("TEST_DATABASE_URL must be set to run tests");
+        let conn = PgConnection::establish(&database_url).unwrap();
+        conn.begin_test_transaction().unwrap();
+        conn
+    }
+
+    fn user(conn: &PgConnection) -> User {
+        NewUser::new(1, "foo", None, None, None, "foo")
+            .create_or_update(conn).unwrap()
+    }
+
+    fn krate(conn: &PgConnection, user: &User, name: &str) -> Crate {
+        NewCrate { name: name, ..NewCrate::default() }
+            .create_or_update(conn, None, user.id).unwrap()
+    }
+
+    fn version(conn: &PgConnection, krate: &Crate, num: &str) -> Version {
+        let num = semver::Version::parse(num).unwrap();
+        NewVersion::new(krate.id, &num, &HashMap::new()).unwrap()
+            .save(conn, &[]).unwrap()
+    }
+
+    fn new_dep<'a>(version: &Version, krate: &Crate) -> NewDependency<'a> {
+        NewDependency {
+            version_id: version.id,
+            crate_id: krate.id,
+            req: ">= 0".into(),
+            optional: false,
+            default_features: true,
+            features: Vec::new(),
+            target: None,
+            kind: Kind::Normal as i32,
+            req_is_raw: false,
+        }
+    }
+
+    fn insert_dep(conn: &PgConnection, dep: &NewDependency) -> Dependency {
+        use diesel::insert;
+
+        insert(dep).into(dependencies::table).get_result(conn).unwrap()
+    }
+
+    fn depend(conn: &PgConnection, version: &Version, krate: &Crate) -> Dependency {
+        insert_dep(conn, &new_dep(version, krate))
+    }
+
+    fn crate_dep(name: &str, req: &str) -> CrateDependency {
+        CrateDependency {
+            optional: false,
+            default_features: true,
+            name: CrateName(name.to_string()),
+            features: Vec::new(),
+            version_req: CrateVersionReq::parse(req).unwrap(),
+            target: None,
+            kind: None,
+        }
+    }
+
+    fn dependency(req: &str) -> Dependency {
+        Dependency {
+            id: 0,
+            version_id: 0,
+            crate_id: 0,
+            req: semver::VersionReq::parse(req).unwrap(),
+            optional: false,
+            default_features: true,
+            features: Vec::new(),
+            target: None,
+            kind: Kind::Normal,
+            req_was_shimmed: false,
+            raw_req: semver::VersionReq::parse(req).unwrap().to_string(),
+            req_is_raw: false,
+        }
+    }
+
+    fn add(conn: &PgConnection, deps: &[CrateDependency], version: &Version)
+           -> CargoResult<(Vec<Dependency>, Vec<String>)> {
+        add_dependencies(conn, deps, &HashMap::new(), Resolver::V1, version.id,
+                         &DependencyPolicy::default())
+    }
+
+    fn feature_map(features: &[(&str, &[&str])]) -> HashMap<CrateName, Vec<Feature>> {
+        features.iter().map(|&(name, values)| {
+            let values = values.iter().map(|v| Feature(v.to_string())).collect();
+            (CrateName(name.to_string()), values)
+        }).collect()
+    }
+
+    /// An audit hook keeping everything it's told about
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<AuditEvent>>,
+        signals: Mutex<Vec<Signal>>,
+    }
+
+    impl AuditHook for Recorder {
+        fn record(&self, event: &AuditEvent) {
+            self.events.lock().unwrap().push(event.clone());
+        }
+
+        fn signal(&self, signal: &Signal) {
+            self.signals.lock().unwrap().push(signal.clone());
+        }
+    }
+
+    fn record_audits(policy: &mut DependencyPolicy) -> Arc<Recorder> {
+        let recorder = Arc::new(Recorder::default());
+        policy.audit_hook = Some(recorder.clone() as Arc<AuditHook>);
+        recorder
+    }
+
+    #[test]
+    fn add_dependencies_trims_padded_requirements() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_padded");
+        let bar = krate(&conn, &user, "bar_padded");
+        let v = version(&conn, &bar, "1.0.0");
+
+        let deps = vec![crate_dep("foo_padded", " ^1.0 ")];
+        let (deps, warnings) = add(&conn, &deps, &v).unwrap();
+        assert_eq!(1, deps.len());
+        assert_eq!(foo.id, deps[0].crate_id);
+        assert_eq!("^1.0", deps[0].req.to_string());
+        assert_eq!(1, warnings.len());
+        assert!(warnings[0].contains("whitespace"), "{:?}", warnings);
+    }
+
+    #[test]
+    fn req_operators_lists_each_comparator() {
+        assert_eq!(vec![Op::GreaterEq, Op::Less],
+                   dependency(">=1, <2").req_operators());
+        assert_eq!(vec![Op::Caret], dependency("^1.0").req_operators());
+        assert_eq!(vec![Op::Caret], dependency("1.0").req_operators());
+    }
+
+    #[test]
+    fn add_dependencies_allows_one_entry_per_kind() {
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "foo_kinds");
+        let bar = krate(&conn, &user, "bar_kinds");
+        let v = version(&conn, &bar, "1.0.0");
+
+        let mut dev = crate_dep("foo_kinds", "^1.0");
+        dev.kind = Some(Kind::Dev);
+        let deps = vec![crate_dep("foo_kinds", "^1.0"), dev];
+        let (deps, _) = add(&conn, &deps, &v).unwrap();
+        assert_eq!(2, deps.len());
+
+        let v = version(&conn, &bar, "1.1.0");
+        let mut dev = crate_dep("foo_kinds", "^1.0");
+        dev.kind = Some(Kind::Dev);
+        let mut dev2 = crate_dep("foo_kinds", "^1.1");
+        dev2.kind = Some(Kind::Dev);
+        let err = add(&conn, &[dev, dev2], &v).err().unwrap();
+        assert!(err.to_string().contains("more than once"), "{}", err);
+    }
+
+    #[test]
+    fn encodable_summary_omits_heavy_fields() {
+        use rustc_serialize::json::{self, Json};
+
+        let mut dep = dependency("^1.0");
+        dep.optional = true;
+        dep.features = vec!["std".to_string()];
+        dep.target = Some("cfg(unix)".to_string());
+
+        let summary = dep.encodable_summary("foo");
+        assert_eq!("foo", summary.crate_name);
+        assert_eq!("^1.0", summary.req);
+        assert_eq!(Kind::Normal, summary.kind);
+        assert!(summary.optional);
+
+        let json = Json::from_str(&json::encode(&summary).unwrap()).unwrap();
+        let json = json.as_object().unwrap();
+        assert_eq!(4, json.len());
+        assert!(!json.contains_key("features"));
+        assert!(!json.contains_key("target"));
+    }
+
+    #[test]
+    fn add_dependencies_rejects_invalid_cfg_targets() {
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "foo_cfg");
+        let bar = krate(&conn, &user, "bar_cfg");
+        let v = version(&conn, &bar, "1.0.0");
+
+        let mut dep = crate_dep("foo_cfg", "^1.0");
+        dep.target = Some("cfg(true)".to_string());
+        let err = add(&conn, &[dep], &v).err().unwrap();
+        assert!(err.to_string().contains("boolean literal `true`"), "{}", err);
+
+        let mut dep = crate_dep("foo_cfg", "^1.0");
+        dep.target = Some(r#"cfg(all(unix, target_pointer_width = "64"))"#.to_string());
+        let (deps, _) = add(&conn, &[dep], &v).unwrap();
+        assert_eq!(1, deps.len());
+    }
+
+    #[test]
+    fn niche_platform_dependencies_skips_common_targets() {
+        let conn = connection();
+        let user = user(&conn);
+        let redox = krate(&conn, &user, "redox_niche");
+        let libc = krate(&conn, &user, "libc_niche");
+        let any = krate(&conn, &user, "any_niche");
+        let v = version(&conn, &krate(&conn, &user, "bar_niche"), "1.0.0");
+
+        let mut dep = new_dep(&v, &redox);
+        dep.target = Some(r#"cfg(target_os = "redox")"#);
+        let redox_dep = insert_dep(&conn, &dep);
+        let mut dep = new_dep(&v, &libc);
+        dep.target = Some("cfg(unix)");
+        insert_dep(&conn, &dep);
+        depend(&conn, &v, &any);
+
+        let niche = niche_platform_dependencies(&conn, v.id).unwrap();
+        assert_eq!(vec![redox_dep.id], niche.iter().map(|d| d.id).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn normalize_matches_canonical_twin() {
+        let mut messy = dependency(">=1.0,<2.0");
+        messy.features = vec!["std".to_string(), "derive".to_string(), "std".to_string()];
+        messy.target = Some("cfg(any( unix,windows ))".to_string());
+
+        let mut twin = dependency(">= 1.0, < 2.0");
+        twin.features = vec!["derive".to_string(), "std".to_string()];
+        twin.target = Some("cfg(any(unix, windows))".to_string());
+
+        messy.normalize();
+        assert_eq!(twin, messy);
+
+        twin.normalize();
+        assert_eq!(twin, messy);
+    }
+
+    #[test]
+    fn dependencies_etag_changes_with_dependencies() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_etag");
+        let baz = krate(&conn, &user, "baz_etag");
+        let v = version(&conn, &krate(&conn, &user, "bar_etag"), "1.0.0");
+        depend(&conn, &v, &foo);
+
+        let etag = dependencies_etag(&conn, v.id).unwrap();
+        assert_eq!(etag, dependencies_etag(&conn, v.id).unwrap());
+
+        depend(&conn, &v, &baz);
+        assert!(etag != dependencies_etag(&conn, v.id).unwrap());
+    }
+
+    #[test]
+    fn add_dependencies_checks_weak_feature_references() {
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "foo_weak");
+        let bar = krate(&conn, &user, "bar_weak");
+        let features = feature_map(&[("serde", &["foo_weak?/serde"])]);
+        let policy = DependencyPolicy::default();
+
+        let v = version(&conn, &bar, "1.0.0");
+        let mut dep = crate_dep("foo_weak", "^1.0");
+        dep.optional = true;
+        assert!(add_dependencies(&conn, &[dep], &features, Resolver::V1, v.id, &policy).is_ok());
+
+        let v = version(&conn, &bar, "1.1.0");
+        let dep = crate_dep("foo_weak", "^1.0");
+        let err = add_dependencies(&conn, &[dep], &features, Resolver::V1, v.id, &policy)
+            .err().unwrap();
+        assert!(err.to_string().contains("not an optional dependency"), "{}", err);
+
+        let v = version(&conn, &bar, "1.2.0");
+        let err = add_dependencies(&conn, &[], &features, Resolver::V1, v.id, &policy)
+            .err().unwrap();
+        assert!(err.to_string().contains("is not a dependency"), "{}", err);
+    }
+
+    #[test]
+    fn resolve_names_and_downloads_batches_lookups() {
+        use diesel::update;
+
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_resolve");
+        let bar = krate(&conn, &user, "bar_resolve");
+        krate(&conn, &user, "baz_resolve");
+        update(crates::table.find(foo.id)).set(crates::downloads.eq(10))
+            .execute(&conn).unwrap();
+
+        let resolved = resolve_names_and_downloads(&conn, &[foo.id, bar.id]).unwrap();
+        assert_eq!(2, resolved.len());
+        assert_eq!(("foo_resolve".to_string(), 10), resolved[&foo.id]);
+        assert_eq!(("bar_resolve".to_string(), 0), resolved[&bar.id]);
+    }
+
+    #[test]
+    fn is_pre_one_caret_detects_zero_major() {
+        assert!(dependency("^0.1.0").is_pre_one_caret());
+        assert!(dependency("^0.0.1").is_pre_one_caret());
+        assert!(dependency("0.3").is_pre_one_caret());
+        assert!(!dependency("^1.0.0").is_pre_one_caret());
+        assert!(!dependency("~0.1.0").is_pre_one_caret());
+    }
+
+    #[test]
+    fn to_cargo_add_spec_omits_defaults() {
+        assert_eq!("foo@^1.0", dependency("^1.0").to_cargo_add_spec("foo"));
+
+        let mut dep = dependency("^1.0");
+        dep.kind = Kind::Dev;
+        dep.default_features = false;
+        dep.features = vec!["a".to_string(), "b".to_string()];
+        dep.optional = true;
+        dep.target = Some("cfg(unix)".to_string());
+        assert_eq!("foo@^1.0 --dev --no-default-features --features a,b --optional \
+                    --target 'cfg(unix)'",
+                   dep.to_cargo_add_spec("foo"));
+
+        let mut dep = dependency(">= 1, < 2");
+        dep.kind = Kind::Build;
+        assert_eq!("'foo@>= 1, < 2' --build", dep.to_cargo_add_spec("foo"));
+    }
+
+    #[test]
+    fn mergeable_target_dependencies_requires_full_coverage() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_merge");
+        let baz = krate(&conn, &user, "baz_merge");
+        let v = version(&conn, &krate(&conn, &user, "bar_merge"), "1.0.0");
+
+        for &target in &["cfg(unix)", "cfg(windows)"] {
+            let mut dep = new_dep(&v, &foo);
+            dep.target = Some(target);
+            insert_dep(&conn, &dep);
+        }
+        for &target in &["cfg(unix)", r#"cfg(target_os = "redox")"#] {
+            let mut dep = new_dep(&v, &baz);
+            dep.target = Some(target);
+            insert_dep(&conn, &dep);
+        }
+
+        assert_eq!(vec!["foo_merge".to_string()],
+                   mergeable_target_dependencies(&conn, v.id).unwrap());
+    }
+
+    #[test]
+    fn add_dependencies_applies_default_features_policy() {
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "foo_nodefault");
+        let bar = krate(&conn, &user, "bar_nodefault");
+        let mut policy = DependencyPolicy::default();
+        policy.normal.deny_bare_no_default_features = true;
+
+        let v = version(&conn, &bar, "1.0.0");
+        let mut dep = crate_dep("foo_nodefault", "^1.0");
+        dep.default_features = false;
+        let err = add_dependencies(&conn, &[dep], &HashMap::new(), Resolver::V1, v.id,
+                                   &policy).err().unwrap();
+        assert!(err.to_string().contains("disables default features"), "{}", err);
+
+        let mut dep = crate_dep("foo_nodefault", "^1.0");
+        dep.default_features = false;
+        dep.kind = Some(Kind::Dev);
+        let (deps, _) = add_dependencies(&conn, &[dep], &HashMap::new(), Resolver::V1,
+                                         v.id, &policy).unwrap();
+        assert_eq!(1, deps.len());
+    }
+
+    #[test]
+    fn dependency_surface_counts_each_category() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_surface");
+        let baz = krate(&conn, &user, "baz_surface");
+        let v = version(&conn, &krate(&conn, &user, "bar_surface"), "1.0.0");
+
+        let mut dep = new_dep(&v, &foo);
+        dep.optional = true;
+        insert_dep(&conn, &dep);
+        let mut dep = new_dep(&v, &foo);
+        dep.kind = Kind::Dev as i32;
+        insert_dep(&conn, &dep);
+        let mut dep = new_dep(&v, &baz);
+        dep.kind = Kind::Build as i32;
+        dep.target = Some("cfg(unix)");
+        insert_dep(&conn, &dep);
+        let mut dep = new_dep(&v, &baz);
+        dep.target = Some("cfg(windows)");
+        insert_dep(&conn, &dep);
+
+        let expected = DependencySurface {
+            normal: 2,
+            build: 1,
+            dev: 1,
+            optional: 1,
+            platform_specific: 2,
+            distinct_crates: 2,
+        };
+        assert_eq!(expected, dependency_surface(&conn, v.id).unwrap());
+    }
+
+    #[test]
+    fn rejects_ambiguous_crate_names() {
+        fn krate(id: i32, name: &str) -> Crate {
+            let now = ::time::Timespec::new(0, 0);
+            Crate {
+                id: id,
+                name: name.to_string(),
+                updated_at: now,
+                created_at: now,
+                downloads: 0,
+                description: None,
+                homepage: None,
+                documentation: None,
+                readme: None,
+                license: None,
+                repository: None,
+                max_upload_size: None,
+            }
+        }
+
+        assert!(check_unique_canonical_names(&[krate(1, "foo_dup"), krate(2, "bar_dup")])
+                .is_ok());
+        let err = check_unique_canonical_names(&[krate(1, "foo_dup"), krate(2, "Foo-Dup")])
+            .err().unwrap();
+        assert!(err.to_string().contains("share the canonical name"), "{}", err);
+    }
+
+    #[test]
+    fn add_dependencies_audits_rejections() {
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "foo_audit");
+        let v = version(&conn, &krate(&conn, &user, "bar_audit"), "1.0.0");
+        let mut policy = DependencyPolicy::default();
+        let recorder = record_audits(&mut policy);
+
+        let deps = [crate_dep("foo_audit", " * ")];
+        assert!(add_dependencies(&conn, &deps, &HashMap::new(), Resolver::V1, v.id, &policy)
+                .is_err());
+
+        let events = recorder.events.lock().unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!("foo_audit", events[0].dependency);
+        assert_eq!(" * ", events[0].original);
+        assert_eq!(AuditAction::Rejected, events[0].action);
+        assert!(events[0].reason.contains("wildcard"), "{}", events[0].reason);
+    }
+
+    #[test]
+    fn versions_have_same_dependencies_ignores_order() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_same");
+        let baz = krate(&conn, &user, "baz_same");
+        let bar = krate(&conn, &user, "bar_same");
+        let v1 = version(&conn, &bar, "1.0.0");
+        let v2 = version(&conn, &bar, "1.0.1");
+        let v3 = version(&conn, &bar, "1.1.0");
+        depend(&conn, &v1, &foo);
+        depend(&conn, &v1, &baz);
+        depend(&conn, &v2, &baz);
+        depend(&conn, &v2, &foo);
+        depend(&conn, &v3, &foo);
+
+        assert!(versions_have_same_dependencies(&conn, v1.id, v2.id).unwrap());
+        assert!(!versions_have_same_dependencies(&conn, v1.id, v3.id).unwrap());
+    }
+
+    #[test]
+    fn build_flags_legacy_requirements() {
+        fn row(req: &str) -> <Dependency as Queryable<dependencies::SqlType, Pg>>::Row {
+            (1, 1, 1, req.to_string(), false, true, Vec::new(), None, 0, false)
+        }
+
+        let dep = Dependency::build(row(">= 1.0 < 2.0"));
+        assert!(dep.req_was_shimmed);
+        assert_eq!(semver::VersionReq::parse(">= 1.0, < 2.0").unwrap(), dep.req);
+
+        let dep = Dependency::build(row(">= 1.0, < 2.0"));
+        assert!(!dep.req_was_shimmed);
+    }
+
+    #[test]
+    fn add_dependencies_ids_returns_new_ids() {
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "foo_ids");
+        krate(&conn, &user, "baz_ids");
+        let v = version(&conn, &krate(&conn, &user, "bar_ids"), "1.0.0");
+
+        let deps = [crate_dep("foo_ids", "^1.0"), crate_dep("baz_ids", "^0.2")];
+        let mut ids = add_dependencies_ids(&conn, &deps, v.id).unwrap();
+        ids.sort();
+        let inserted = find_by_version_id(&conn, v.id).unwrap()
+            .into_iter().map(|d| d.id).collect::<Vec<_>>();
+        assert_eq!(2, ids.len());
+        assert_eq!(inserted, ids);
+    }
+
+    #[test]
+    fn add_dependencies_leaves_nothing_behind_on_failure() {
+        use diesel::Connection;
+
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "foo_partial");
+        krate(&conn, &user, "baz_partial");
+        let v = version(&conn, &krate(&conn, &user, "bar_partial"), "1.0.0");
+        conn.execute("ALTER TABLE dependencies ADD CONSTRAINT no_baz_partial \
+                      CHECK (req <> '^0.2')").unwrap();
+
+        let deps = [crate_dep("foo_partial", "^1.0"), crate_dep("baz_partial", "^0.2")];
+        assert!(add(&conn, &deps, &v).is_err());
+        // The surrounding test transaction is still usable, so only the
+        // savepoint was rolled back
+        assert!(find_by_version_id(&conn, v.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn add_dependencies_warns_about_unreferenced_optional_dependencies() {
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "foo_unref");
+        krate(&conn, &user, "baz_unref");
+        let v = version(&conn, &krate(&conn, &user, "bar_unref"), "1.0.0");
+        let features = feature_map(&[("json", &["dep:baz_unref"])]);
+        let policy = DependencyPolicy::default();
+
+        let mut foo = crate_dep("foo_unref", "^1.0");
+        foo.optional = true;
+        let mut baz = crate_dep("baz_unref", "^1.0");
+        baz.optional = true;
+        let (_, warnings) = add_dependencies(&conn, &[foo, baz], &features, Resolver::V2,
+                                             v.id, &policy).unwrap();
+        assert_eq!(1, warnings.len());
+        assert!(warnings[0].contains("`foo_unref` is not enabled by any feature"),
+                "{}", warnings[0]);
+    }
+
+    #[test]
+    fn add_dependencies_warns_once_when_there_are_no_features() {
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "foo_featureless");
+        krate(&conn, &user, "baz_featureless");
+        krate(&conn, &user, "quux_featureless");
+        let v = version(&conn, &krate(&conn, &user, "bar_featureless"), "1.0.0");
+        let policy = DependencyPolicy::default();
+
+        let mut foo = crate_dep("foo_featureless", "^1.0");
+        foo.optional = true;
+        let mut baz = crate_dep("baz_featureless", "^1.0");
+        baz.optional = true;
+        let quux = crate_dep("quux_featureless", "^1.0");
+        let (_, warnings) = add_dependencies(&conn, &[foo, baz, quux], &HashMap::new(),
+                                             Resolver::V2, v.id, &policy).unwrap();
+        assert_eq!(warnings, vec!["the crate defines no features, so optional dependencies \
+                                   `foo_featureless`, `baz_featureless` are unreachable"]);
+    }
+
+    #[test]
+    fn dependency_jaccard_compares_crate_sets() {
+        let conn = connection();
+        let user = user(&conn);
+        let a = krate(&conn, &user, "a_jaccard");
+        let b = krate(&conn, &user, "b_jaccard");
+        let c = krate(&conn, &user, "c_jaccard");
+        let d = krate(&conn, &user, "d_jaccard");
+        let v1 = version(&conn, &krate(&conn, &user, "foo_jaccard"), "1.0.0");
+        let v2 = version(&conn, &krate(&conn, &user, "bar_jaccard"), "1.0.0");
+        for krate in &[&a, &b, &c] {
+            depend(&conn, &v1, krate);
+        }
+        for krate in &[&b, &c, &d] {
+            depend(&conn, &v2, krate);
+        }
+
+        // {b, c} shared out of {a, b, c, d}
+        assert_eq!(0.5, dependency_jaccard(&conn, v1.id, v2.id).unwrap());
+        assert_eq!(1.0, dependency_jaccard(&conn, v1.id, v1.id).unwrap());
+    }
+
+    #[test]
+    fn add_dependencies_allows_split_requirements_per_target() {
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "foo_split");
+        let bar = krate(&conn, &user, "bar_split");
+
+        let v = version(&conn, &bar, "1.0.0");
+        let mut unix = crate_dep("foo_split", "^1");
+        unix.target = Some("cfg(unix)".to_string());
+        let mut windows = crate_dep("foo_split", "^2");
+        windows.target = Some("cfg(windows)".to_string());
+        let (deps, _) = add(&conn, &[unix, windows], &v).unwrap();
+        assert_eq!(2, deps.len());
+
+        let v = version(&conn, &bar, "1.1.0");
+        let deps = [crate_dep("foo_split", "^1"), crate_dep("foo_split", "^2")];
+        let err = add(&conn, &deps, &v).err().unwrap();
+        assert!(err.to_string().contains("contradictory requirements `^1` and `^2`"),
+                "{}", err);
+
+        // The same requirement spelled differently is only a duplicate
+        let v = version(&conn, &bar, "1.2.0");
+        let deps = [crate_dep("foo_split", "1"), crate_dep("foo_split", "^1")];
+        let err = add(&conn, &deps, &v).err().unwrap();
+        assert!(err.to_string().contains("declared more than once as a normal dependency"),
+                "{}", err);
+        assert!(!err.to_string().contains("contradictory"), "{}", err);
+    }
+
+    #[test]
+    fn newly_added_dependencies_compares_latest_two_versions() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_fresh");
+        let baz = krate(&conn, &user, "baz_fresh");
+        let quux = krate(&conn, &user, "quux_fresh");
+        let bar = krate(&conn, &user, "bar_fresh");
+        let v1 = version(&conn, &bar, "1.0.0");
+        let v2 = version(&conn, &bar, "1.1.0");
+        depend(&conn, &v1, &foo);
+        depend(&conn, &v1, &quux);
+        depend(&conn, &v2, &foo);
+        depend(&conn, &v2, &baz);
+
+        assert_eq!(vec!["baz_fresh".to_string()],
+                   newly_added_dependencies(&conn, bar.id).unwrap());
+    }
+
+    #[test]
+    fn add_dependencies_checks_requested_features_exist() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_features");
+        let mut features = HashMap::new();
+        features.insert("json".to_string(), Vec::new());
+        NewVersion::new(foo.id, &semver::Version::parse("1.0.0").unwrap(), &features)
+            .unwrap().save(&conn, &[]).unwrap();
+        let bar = krate(&conn, &user, "bar_features");
+
+        let v = version(&conn, &bar, "1.0.0");
+        let mut dep = crate_dep("foo_features", "^1.0");
+        dep.features = vec![Feature("json".to_string())];
+        assert!(add(&conn, &[dep], &v).is_ok());
+
+        let v = version(&conn, &bar, "1.1.0");
+        let mut dep = crate_dep("foo_features", "^1.0");
+        dep.features = vec![Feature("jsno".to_string())];
+        let err = add(&conn, &[dep], &v).err().unwrap();
+        assert!(err.to_string().contains("doesn't define: jsno"), "{}", err);
+    }
+
+    #[test]
+    fn kind_stable_codes_round_trip() {
+        for &kind in &[Kind::Normal, Kind::Build, Kind::Dev] {
+            assert_eq!(Some(kind), Kind::from_stable_code(kind.stable_code()));
+        }
+        assert_eq!(None, Kind::from_stable_code(0));
+        assert_eq!(None, Kind::from_stable_code(4));
+    }
+
+    #[test]
+    fn add_dependencies_flags_facade_crates() {
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "foo_facade");
+        krate(&conn, &user, "baz_facade");
+        let v = version(&conn, &krate(&conn, &user, "bar_facade"), "1.0.0");
+        let mut facades = HashMap::new();
+        facades.insert("foo_facade".to_string(), "foo_core".to_string());
+        let mut policy = DependencyPolicy::default();
+        policy.facades = Some(Arc::new(facades) as Arc<FacadeCrates>);
+
+        let deps = [crate_dep("foo_facade", "^1.0"), crate_dep("baz_facade", "^1.0")];
+        let (_, warnings) = add_dependencies(&conn, &deps, &HashMap::new(), Resolver::V1,
+                                             v.id, &policy).unwrap();
+        assert_eq!(vec!["dependency `foo_facade` only re-exports `foo_core`, consider \
+                         depending on `foo_core` directly".to_string()], warnings);
+    }
+
+    #[test]
+    fn find_loads_dependency_by_id() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_find");
+        let v = version(&conn, &krate(&conn, &user, "bar_find"), "1.0.0");
+        let mut dep = new_dep(&v, &foo);
+        dep.target = Some("cfg(unix)");
+        let inserted = insert_dep(&conn, &dep);
+
+        assert_eq!(inserted, find(&conn, inserted.id).unwrap());
+        let err = find(&conn, inserted.id + 1).err().unwrap();
+        assert!(err.to_string().contains("no dependency with id"), "{}", err);
+    }
+
+    #[test]
+    fn dependency_looseness_averages_normal_and_build() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_loose");
+        let baz = krate(&conn, &user, "baz_loose");
+        let v = version(&conn, &krate(&conn, &user, "bar_loose"), "1.0.0");
+
+        let mut dep = new_dep(&v, &foo);
+        dep.req = "= 1.2.3".to_string();
+        insert_dep(&conn, &dep);
+        let mut dep = new_dep(&v, &baz);
+        dep.req = "^1.0".to_string();
+        dep.kind = Kind::Build as i32;
+        insert_dep(&conn, &dep);
+        let mut dep = new_dep(&v, &baz);
+        dep.req = "= 1.0.0".to_string();
+        dep.kind = Kind::Dev as i32;
+        insert_dep(&conn, &dep);
+
+        let score = dependency_looseness(&conn, v.id).unwrap();
+        assert!(score > 0.25 && score < 0.5, "{}", score);
+    }
+
+    #[test]
+    fn encodable_includes_parsed_target() {
+        use rustc_serialize::json;
+
+        let mut dep = dependency("^1.0");
+        dep.target = Some(r#"cfg(all(unix, not(target_arch = "x86")))"#.to_string());
+        let target_cfg = dep.encodable("foo", None).target_cfg.unwrap();
+        assert_eq!(concat!(r#"{"op":"all","args":["#,
+                           r#"{"op":"name","args":[],"name":"unix","value":null},"#,
+                           r#"{"op":"not","args":["#,
+                           r#"{"op":"key_pair","args":[],"name":"target_arch","value":"x86"}"#,
+                           r#"],"name":null,"value":null}"#,
+                           r#"],"name":null,"value":null}"#),
+                   json::encode(&target_cfg).unwrap());
+
+        let mut dep = dependency("^1.0");
+        dep.target = Some("x86_64-pc-windows-gnu".to_string());
+        assert!(dep.encodable("foo", None).target_cfg.is_none());
+    }
+
+    #[test]
+    fn add_dependencies_limits_total_features() {
+        let conn = connection();
+        let user = user(&conn);
+        let v = version(&conn, &krate(&conn, &user, "bar_many"), "1.0.0");
+        let mut policy = DependencyPolicy::default();
+        policy.max_total_features = Some(10);
+        let recorder = record_audits(&mut policy);
+
+        let deps = ["a_many", "b_many", "c_many"].iter().map(|name| {
+            krate(&conn, &user, name);
+            let mut dep = crate_dep(name, "^1.0");
+            dep.features = (0..4).map(|i| Feature(format!("f{}", i))).collect();
+            dep
+        }).collect::<Vec<_>>();
+        let err = add_dependencies(&conn, &deps, &HashMap::new(), Resolver::V1, v.id, &policy)
+            .err().unwrap();
+        assert!(err.to_string().contains("enable 12 features in total"), "{}", err);
+
+        let events = recorder.events.lock().unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!("", events[0].dependency);
+        assert_eq!("12", events[0].original);
+        assert_eq!(AuditAction::Rejected, events[0].action);
+        assert!(events[0].reason.contains("enable 12 features in total"), "{}",
+                events[0].reason);
+    }
+
+    #[test]
+    fn optional_candidate_dependencies_finds_single_feature_deps() {
+        let conn = connection();
+        let user = user(&conn);
+        let serde = krate(&conn, &user, "serde_candidate");
+        let libc = krate(&conn, &user, "libc_candidate");
+        let v = version(&conn, &krate(&conn, &user, "bar_candidate"), "1.0.0");
+        depend(&conn, &v, &serde);
+        depend(&conn, &v, &libc);
+        let mut features = HashMap::new();
+        features.insert("json".to_string(), vec!["serde_candidate/derive".to_string()]);
+
+        assert_eq!(vec!["serde_candidate".to_string()],
+                   optional_candidate_dependencies(&conn, v.id, &features).unwrap());
+    }
+
+    #[test]
+    fn preserve_raw_req_keeps_author_spelling() {
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "foo_raw");
+        let bar = krate(&conn, &user, "bar_raw");
+        let mut policy = DependencyPolicy::default();
+        policy.preserve_raw_req = true;
+
+        let v = version(&conn, &bar, "1.0.0");
+        let deps = [crate_dep("foo_raw", "1")];
+        let (deps, _) = add_dependencies(&conn, &deps, &HashMap::new(), Resolver::V1, v.id,
+                                         &policy).unwrap();
+        let dep = deps.into_iter().next().unwrap();
+        assert_eq!("1", dep.encodable_summary("foo_raw").req);
+        assert_eq!("1", dep.git_encode("foo_raw").req);
+
+        let v = version(&conn, &bar, "1.1.0");
+        let (deps, _) = add(&conn, &[crate_dep("foo_raw", "1")], &v).unwrap();
+        let git = deps.into_iter().next().unwrap().git_encode("foo_raw");
+        assert_eq!("^1", git.req);
+    }
+
+    #[test]
+    fn stored_spelling_is_only_published_when_raw() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_unmarked");
+        let v = version(&conn, &krate(&conn, &user, "bar_unmarked"), "1.0.0");
+        let mut dep = new_dep(&v, &foo);
+        dep.req = ">=1.0".into();
+        let dep = insert_dep(&conn, &dep);
+
+        assert_eq!(">= 1.0", dep.published_req());
+        assert_eq!(">= 1.0", dep.encodable_summary("foo_unmarked").req);
+        assert_eq!(">= 1.0", dep.git_encode("foo_unmarked").req);
+    }
+
+    #[test]
+    fn lagging_dependencies_reports_old_majors() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_lag");
+        let baz = krate(&conn, &user, "baz_lag");
+        version(&conn, &foo, "1.0.0");
+        version(&conn, &foo, "3.1.0");
+        version(&conn, &baz, "2.0.0");
+        let v = version(&conn, &krate(&conn, &user, "bar_lag"), "1.0.0");
+        let mut dep = new_dep(&v, &foo);
+        dep.req = "^1".to_string();
+        insert_dep(&conn, &dep);
+        let mut dep = new_dep(&v, &baz);
+        dep.req = "^2".to_string();
+        insert_dep(&conn, &dep);
+
+        assert_eq!(vec![("foo_lag".to_string(), "^1".to_string(), "3".to_string())],
+                   lagging_dependencies(&conn, v.id).unwrap());
+    }
+
+    #[test]
+    fn minimal_version_resolution_picks_oldest_match() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_minimal");
+        let baz = krate(&conn, &user, "baz_minimal");
+        for num in &["1.0.0", "1.2.0", "1.3.0"] {
+            version(&conn, &foo, num);
+        }
+        for num in &["0.9.0", "1.4.0", "1.5.0"] {
+            version(&conn, &baz, num);
+        }
+        let v = version(&conn, &krate(&conn, &user, "bar_minimal"), "1.0.0");
+        let mut dep = new_dep(&v, &foo);
+        dep.req = "^1.2".to_string();
+        insert_dep(&conn, &dep);
+        // Nothing was ever published as 1.1.0, so 1.4.0 is the oldest match
+        let mut dep = new_dep(&v, &baz);
+        dep.req = "^1.1".to_string();
+        insert_dep(&conn, &dep);
+
+        let v120 = semver::Version::parse("1.2.0").unwrap();
+        let v140 = semver::Version::parse("1.4.0").unwrap();
+        assert_eq!(vec![("baz_minimal".to_string(), v140), ("foo_minimal".to_string(), v120)],
+                   minimal_version_resolution(&conn, v.id).unwrap());
+    }
+
+    #[test]
+    fn index_req_string_matches_cargo() {
+        // Requirements as they appear in the crates.io index
+        for &req in &["^1.0", "^0.2.3", "= 0.3.1", "~1.2", ">= 0.2.0, < 0.4.0",
+                      ">= 1, < 2", "*", "0.1.*", "^1.0.0-alpha.2", "<= 3.1"] {
+            assert_eq!(req, dependency(req).index_req_string());
+        }
+        assert_eq!("^1.0", dependency("1.0").index_req_string());
+    }
+
+    #[test]
+    fn normal_build_overlaps_reports_crates_in_both() {
+        let conn = connection();
+        let user = user(&conn);
+        let cc = krate(&conn, &user, "cc_overlap");
+        let libc = krate(&conn, &user, "libc_overlap");
+        let v = version(&conn, &krate(&conn, &user, "bar_overlap"), "1.0.0");
+        depend(&conn, &v, &cc);
+        depend(&conn, &v, &libc);
+        let mut dep = new_dep(&v, &cc);
+        dep.kind = Kind::Build as i32;
+        insert_dep(&conn, &dep);
+
+        assert_eq!(vec!["cc_overlap".to_string()],
+                   normal_build_overlaps(&conn, v.id).unwrap());
+    }
+
+    #[test]
+    fn add_dependencies_applies_operator_policy() {
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "foo_ops");
+        let bar = krate(&conn, &user, "bar_ops");
+        let mut policy = DependencyPolicy::default();
+        policy.build.allowed_ops = Some(vec![Op::Caret, Op::Exact].into_iter().collect());
+
+        let v = version(&conn, &bar, "1.0.0");
+        let mut dep = crate_dep("foo_ops", ">=1");
+        dep.kind = Some(Kind::Build);
+        let err = add_dependencies(&conn, &[dep], &HashMap::new(), Resolver::V1, v.id,
+                                   &policy).err().unwrap();
+        assert!(err.to_string().contains("build dependency `foo_ops` uses the `>=` operator"),
+                "{}", err);
+
+        let mut dep = crate_dep("foo_ops", "^1");
+        dep.kind = Some(Kind::Build);
+        assert!(add_dependencies(&conn, &[dep], &HashMap::new(), Resolver::V1, v.id,
+                                 &policy).is_ok());
+    }
+
+    #[test]
+    fn effectively_dev_dependencies_finds_test_only_targets() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_testonly");
+        let baz = krate(&conn, &user, "baz_testonly");
+        let v = version(&conn, &krate(&conn, &user, "bar_testonly"), "1.0.0");
+        let mut dep = new_dep(&v, &foo);
+        dep.target = Some("cfg(test)");
+        insert_dep(&conn, &dep);
+        let mut dep = new_dep(&v, &baz);
+        dep.target = Some("cfg(unix)");
+        insert_dep(&conn, &dep);
+
+        assert_eq!(vec!["foo_testonly".to_string()],
+                   effectively_dev_dependencies(&conn, v.id).unwrap());
+    }
+
+    #[test]
+    fn weak_features_on_missing_dependencies_are_audited() {
+        let conn = connection();
+        let user = user(&conn);
+        let v = version(&conn, &krate(&conn, &user, "bar_weak_audit"), "1.0.0");
+        let features = feature_map(&[("serde", &["foo_weak_audit?/serde"])]);
+        let mut policy = DependencyPolicy::default();
+        let recorder = record_audits(&mut policy);
+
+        assert!(add_dependencies(&conn, &[], &features, Resolver::V1, v.id, &policy).is_err());
+        let events = recorder.events.lock().unwrap();
+        assert_eq!(*events, vec![AuditEvent::without_dependency(
+            "foo_weak_audit?/serde",
+            AuditAction::Rejected,
+            "feature `serde` includes `foo_weak_audit?/serde`, but `foo_weak_audit` is not a \
+             dependency",
+        )]);
+    }
+
+    #[test]
+    fn add_dependencies_signals_unusual_dependency_counts() {
+        let conn = connection();
+        let user = user(&conn);
+        let v = version(&conn, &krate(&conn, &user, "bar_signal"), "1.0.0");
+        let mut policy = DependencyPolicy::default();
+        let recorder = record_audits(&mut policy);
+        policy.dependency_baseline = Some(2);
+
+        let deps = ["a_signal", "b_signal", "c_signal"].iter().map(|name| {
+            krate(&conn, &user, name);
+            crate_dep(name, "^1.0")
+        }).collect::<Vec<_>>();
+        let (deps, _) = add_dependencies(&conn, &deps, &HashMap::new(), Resolver::V1, v.id,
+                                         &policy).unwrap();
+        assert_eq!(3, deps.len());
+        assert_eq!(vec![Signal::ManyDependencies { version_id: v.id, count: 3, baseline: 2 }],
+                   *recorder.signals.lock().unwrap());
+    }
+
+    #[test]
+    fn resolved_dependency_edges_pick_newest_match() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_edges");
+        let baz = krate(&conn, &user, "baz_edges");
+        for num in &["1.0.0", "1.4.0", "2.0.0"] {
+            version(&conn, &foo, num);
+        }
+        version(&conn, &baz, "0.1.0");
+        let v = version(&conn, &krate(&conn, &user, "bar_edges"), "1.0.0");
+        let mut dep = new_dep(&v, &foo);
+        dep.req = "^1.0".to_string();
+        insert_dep(&conn, &dep);
+        let mut dep = new_dep(&v, &baz);
+        dep.req = "^0.2".to_string();
+        dep.kind = Kind::Dev as i32;
+        insert_dep(&conn, &dep);
+
+        let edges = resolved_dependency_edges(&conn, v.id).unwrap();
+        assert_eq!(vec![
+            ResolvedEdge {
+                name: "foo_edges".to_string(),
+                req: "^1.0".to_string(),
+                kind: Kind::Normal,
+                resolved_version: Some(semver::Version::parse("1.4.0").unwrap()),
+            },
+            ResolvedEdge {
+                name: "baz_edges".to_string(),
+                req: "^0.2".to_string(),
+                kind: Kind::Dev,
+                resolved_version: None,
+            },
+        ], edges);
+    }
+
+    #[test]
+    fn dedup_key_ignores_features_but_not_targets() {
+        let mut a = dependency("^1.0");
+        a.target = Some("cfg( unix )".to_string());
+        let mut b = a.clone();
+        b.features = vec!["std".to_string()];
+        b.target = Some("cfg(unix)".to_string());
+        let mut c = a.clone();
+        c.target = Some("cfg(windows)".to_string());
+
+        assert_eq!(a.dedup_key(), b.dedup_key());
+        assert!(a.dedup_key() != c.dedup_key());
+        assert_eq!((0, Kind::Normal, Some("cfg(unix)".to_string())), a.dedup_key());
+    }
+
+    #[test]
+    fn git_encode_emits_parseable_requirements() {
+        for &req in &["^1.0", "1", "~0.2.3", "= 1.2.3", ">= 1, < 2", "< 0.5", "*",
+                      "0.3.*", "^1.0.0-beta.1", "> 0.1.2, <= 0.4"] {
+            let dep = dependency(req);
+            let expected = dep.req.clone();
+            let emitted = dep.git_encode("foo").req;
+            assert_eq!(expected, semver::VersionReq::parse(&emitted).unwrap(), "{}", emitted);
+        }
+    }
+
+    #[test]
+    fn features_by_target_groups_per_target() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_per_target");
+        let v = version(&conn, &krate(&conn, &user, "bar_per_target"), "1.0.0");
+        let mut dep = new_dep(&v, &foo);
+        dep.target = Some("cfg(unix)");
+        dep.features = vec!["std", "unix"];
+        insert_dep(&conn, &dep);
+        let mut dep = new_dep(&v, &foo);
+        dep.target = Some("cfg(windows)");
+        dep.features = vec!["std"];
+        insert_dep(&conn, &dep);
+
+        let mut expected = HashMap::new();
+        expected.insert(Some("cfg(unix)".to_string()),
+                        vec!["std".to_string(), "unix".to_string()]);
+        expected.insert(Some("cfg(windows)".to_string()), vec!["std".to_string()]);
+        assert_eq!(expected, features_by_target(&conn, v.id, "foo-per-target").unwrap());
+    }
+
+    #[test]
+    fn non_default_dependencies_reports_optional_only_deps() {
+        let conn = connection();
+        let user = user(&conn);
+        let serde = krate(&conn, &user, "serde_non_default");
+        let log = krate(&conn, &user, "log_non_default");
+        let libc = krate(&conn, &user, "libc_non_default");
+        let v = version(&conn, &krate(&conn, &user, "foo_non_default"), "1.0.0");
+        depend(&conn, &v, &libc);
+        for krate in &[&serde, &log] {
+            let mut dep = new_dep(&v, krate);
+            dep.optional = true;
+            insert_dep(&conn, &dep);
+        }
+
+        let mut features = HashMap::new();
+        features.insert("default".to_string(), vec!["logging".to_string()]);
+        features.insert("logging".to_string(), vec!["dep:log_non_default".to_string()]);
+        features.insert("serialize".to_string(),
+                        vec!["serde_non_default/derive".to_string()]);
+
+        let active = active_dependencies_under_features(&conn, v.id, &features, &["default"])
+            .unwrap();
+        assert_eq!(active, vec!["libc_non_default", "log_non_default"]);
+        assert_eq!(non_default_dependencies(&conn, v.id, &features).unwrap(),
+                   vec!["serde_non_default"]);
+    }
+
+    #[test]
+    fn format_dependency_diff_lists_changes() {
+        let mut foo = dependency("^1.0");
+        foo.crate_id = 1;
+        let mut bar = dependency("^2.0");
+        bar.crate_id = 2;
+        bar.kind = Kind::Dev;
+        let mut baz = dependency("~0.3");
+        baz.crate_id = 3;
+        baz.target = Some("cfg(unix)".into());
+        let mut names = HashMap::new();
+        names.insert(1, "foo".to_string());
+        names.insert(2, "bar".to_string());
+
+        assert_eq!(format_dependency_diff(&[foo, baz], &[bar], &names),
+                   "+ foo ^1.0 (normal)\n+ #3 ~0.3 (normal, cfg(unix))\n- bar ^2.0 (dev)");
+    }
+
+    #[test]
+    fn dependency_feature_groups_reports_grouped_deps() {
+        let conn = connection();
+        let user = user(&conn);
+        let v = version(&conn, &krate(&conn, &user, "foo_groups"), "1.0.0");
+        for name in &["a_groups", "b_groups", "c_groups"] {
+            let mut dep = new_dep(&v, &krate(&conn, &user, name));
+            dep.optional = true;
+            insert_dep(&conn, &dep);
+        }
+        depend(&conn, &v, &krate(&conn, &user, "d_groups"));
+
+        let mut features = HashMap::new();
+        features.insert("full".to_string(), vec![
+            "a_groups".to_string(),
+            "dep:b_groups".to_string(),
+            "c_groups/std".to_string(),
+            "d_groups/std".to_string(),
+        ]);
+        features.insert("std".to_string(), Vec::new());
+
+        let groups = dependency_feature_groups(&conn, v.id, &features).unwrap();
+        assert_eq!(groups, vec![("full".to_string(), vec![
+            "a_groups".to_string(),
+            "b_groups".to_string(),
+            "c_groups".to_string(),
+        ])]);
+    }
+
+    #[test]
+    fn dependency_names_resolve_canonically() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_canon_dep");
+        let v = version(&conn, &krate(&conn, &user, "bar_canon_dep"), "1.0.0");
+
+        let (deps, _) = add(&conn, &[crate_dep("Foo-Canon-Dep", "^1.0")], &v).unwrap();
+        assert_eq!(deps.len(), 1);
+        assert_eq!(deps[0].crate_id, foo.id);
+
+        let err = add(&conn, &[crate_dep("Missing-Canon-Dep", "^1.0")], &v).err().unwrap();
+        assert!(err.to_string().contains("canonically `missing_canon_dep`"), "{}", err);
+    }
+
+    #[test]
+    fn hygiene_issues_flag_suspicious_dependencies() {
+        fn codes(dep: &Dependency) -> Vec<HygieneCode> {
+            dep.hygiene_issues().into_iter().map(|i| i.code).collect()
+        }
+
+        assert!(codes(&dependency("^1.0")).is_empty());
+        assert_eq!(codes(&dependency("=1.2.3")), vec![HygieneCode::ExactPin]);
+        assert_eq!(codes(&dependency("^0.3")), vec![HygieneCode::TightPreOneCaret]);
+        assert_eq!(codes(&dependency(">= 1.2")), vec![HygieneCode::NoUpperBound]);
+        assert_eq!(codes(&dependency(">= 0")), vec![HygieneCode::MatchesEverything]);
+
+        let mut dep = dependency("^1.0");
+        dep.features = vec!["default".to_string()];
+        assert_eq!(codes(&dep), vec![HygieneCode::RedundantDefaultFeatures]);
+        dep.features = vec!["std".to_string()];
+        assert!(codes(&dep).is_empty());
+    }
+
+    #[test]
+    fn recanonicalize_reqs_rewrites_stale_forms() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_recanon");
+        let bar = krate(&conn, &user, "bar_recanon");
+        let v = version(&conn, &bar, "1.0.0");
+        let mut stale = new_dep(&v, &foo);
+        stale.req = ">=1.0 <2.0".into();
+        let stale = insert_dep(&conn, &stale);
+        let mut current = new_dep(&v, &foo);
+        current.req = "^1.0".into();
+        current.kind = Kind::Dev as i32;
+        insert_dep(&conn, &current);
+
+        assert_eq!(recanonicalize_reqs(&conn, bar.id).unwrap(), 1);
+        let stored = dependencies::table.find(stale.id)
+            .select(dependencies::req)
+            .first::<String>(&conn).unwrap();
+        assert_eq!(stored, ">= 1.0, < 2.0");
+        assert_eq!(recanonicalize_reqs(&conn, bar.id).unwrap(), 0);
+    }
+
+    #[test]
+    fn recanonicalize_reqs_keeps_raw_reqs() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_recanon_raw");
+        let bar = krate(&conn, &user, "bar_recanon_raw");
+        let v = version(&conn, &bar, "1.0.0");
+        let mut raw = new_dep(&v, &foo);
+        raw.req = "1".into();
+        raw.req_is_raw = true;
+        let raw = insert_dep(&conn, &raw);
+
+        assert_eq!(recanonicalize_reqs(&conn, bar.id).unwrap(), 0);
+        assert_eq!("1", find(&conn, raw.id).unwrap().published_req());
+    }
+
+    #[test]
+    fn interceptor_rewrites_and_rejects() {
+        struct Mirror;
+
+        impl DependencyInterceptor for Mirror {
+            fn inspect(&self, dep: &CrateDependency) -> InterceptAction {
+                match &*dep.name {
+                    "upstream_intercept" => {
+                        let mut dep = dep.clone();
+                        dep.name = CrateName("mirror_intercept".to_string());
+                        InterceptAction::Transform(dep)
+                    }
+                    "banned_intercept" => InterceptAction::Reject("banned".to_string()),
+                    _ => InterceptAction::Accept,
+                }
+            }
+        }
+
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "upstream_intercept");
+        krate(&conn, &user, "banned_intercept");
+        let mirror = krate(&conn, &user, "mirror_intercept");
+        let v = version(&conn, &krate(&conn, &user, "foo_intercept"), "1.0.0");
+        let mut policy = DependencyPolicy::default();
+        policy.interceptor = Some(Arc::new(Mirror) as Arc<DependencyInterceptor>);
+
+        let deps = [crate_dep("upstream_intercept", "^1.0")];
+        let (deps, _) = add_dependencies(&conn, &deps, &HashMap::new(), Resolver::V1, v.id,
+                                         &policy).unwrap();
+        assert_eq!(deps[0].crate_id, mirror.id);
+
+        let deps = [crate_dep("banned_intercept", "^1.0")];
+        let err = add_dependencies(&conn, &deps, &HashMap::new(), Resolver::V1, v.id, &policy)
+            .err().unwrap();
+        assert!(err.to_string().contains("banned"), "{}", err);
+    }
+
+    #[test]
+    fn explicit_default_feature() {
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "foo_explicit_default");
+        let v = version(&conn, &krate(&conn, &user, "bar_explicit_default"), "1.0.0");
+
+        let mut dep = crate_dep("foo_explicit_default", "^1.0");
+        dep.features = vec![Feature("default".to_string())];
+        let (_, warnings) = add(&conn, &[dep.clone()], &v).unwrap();
+        assert_eq!(warnings, vec!["dependency `foo_explicit_default` enables the `default` \
+                                   feature, which is already enabled by default"]);
+
+        let v = version(&conn, &krate(&conn, &user, "baz_explicit_default"), "1.0.0");
+        dep.default_features = false;
+        let err = add(&conn, &[dep], &v).err().unwrap();
+        assert!(err.to_string().contains("disables default features but enables"), "{}", err);
+    }
+
+    #[test]
+    fn dependency_columns_line_up() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_columns");
+        let baz = krate(&conn, &user, "baz_columns");
+        let bar = krate(&conn, &user, "bar_columns");
+        let v1 = version(&conn, &bar, "1.0.0");
+        let v2 = version(&conn, &bar, "2.0.0");
+        let mut dep = new_dep(&v2, &baz);
+        dep.req = "^2.0".into();
+        dep.kind = Kind::Dev as i32;
+        insert_dep(&conn, &dep);
+        let mut dep = new_dep(&v1, &foo);
+        dep.req = "^1.0".into();
+        dep.optional = true;
+        dep.default_features = false;
+        dep.target = Some("cfg(unix)");
+        insert_dep(&conn, &dep);
+
+        let columns = dependency_columns(&conn, &[v1.id, v2.id]).unwrap();
+        assert_eq!(columns, DependencyColumns {
+            version_id: vec![v1.id, v2.id],
+            crate_name: vec!["foo_columns".to_string(), "baz_columns".to_string()],
+            req: vec!["^1.0".to_string(), "^2.0".to_string()],
+            kind_code: vec![1, 3],
+            optional: vec![true, false],
+            default_features: vec![false, true],
+            target: vec![Some("cfg(unix)".to_string()), None],
+        });
+    }
+
+    #[test]
+    fn warns_about_exact_pre_release_pins() {
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "foo_pre_pin");
+        let v = version(&conn, &krate(&conn, &user, "bar_pre_pin"), "1.0.0");
+        let (_, warnings) = add(&conn, &[crate_dep("foo_pre_pin", "=1.0.0-alpha.3")], &v)
+            .unwrap();
+        assert_eq!(warnings, vec!["dependency `foo_pre_pin` is pinned to the pre-release \
+                                   `=1.0.0-alpha.3`, which may not stay available once a \
+                                   stable release is published"]);
+
+        let v = version(&conn, &krate(&conn, &user, "baz_pre_pin"), "1.0.0");
+        let (_, warnings) = add(&conn, &[crate_dep("foo_pre_pin", "=1.0.0")], &v).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn allowlist_rejects_uncovered_dependencies() {
+        let conn = connection();
+        let user = user(&conn);
+        krate(&conn, &user, "foo_allowlist");
+        krate(&conn, &user, "baz_allowlist");
+        let v = version(&conn, &krate(&conn, &user, "bar_allowlist"), "1.0.0");
+        let mut allowlist = HashMap::new();
+        allowlist.insert("foo_allowlist".to_string(),
+                         semver::VersionReq::parse("^1.0").unwrap());
+        let mut policy = DependencyPolicy::default();
+        policy.allowlist = Some(Arc::new(allowlist) as Arc<DependencyAllowlist>);
+
+        let deps = [crate_dep("foo_allowlist", "^1.2")];
+        assert!(add_dependencies(&conn, &deps, &HashMap::new(), Resolver::V1, v.id, &policy)
+                .is_ok());
+
+        let deps = [crate_dep("foo_allowlist", "^1.2"), crate_dep("baz_allowlist", "^1.0")];
+        let err = add_dependencies(&conn, &deps, &HashMap::new(), Resolver::V1, v.id, &policy)
+            .err().unwrap();
+        assert!(err.to_string()
+                    .contains("dependencies aren't covered by the allowlist: \
+                               `baz_allowlist ^1.0`"), "{}", err);
+    }
+
+    #[test]
+    fn sort_dependencies_by_names_follows_order() {
+        let mut names = HashMap::new();
+        let mut deps = Vec::new();
+        for (id, name) in vec![(1, "a"), (2, "b"), (3, "c"), (4, "d"), (2, "b")] {
+            names.insert(id, name.to_string());
+            let mut dep = dependency("^1.0");
+            dep.id = deps.len() as i32;
+            dep.crate_id = id;
+            deps.push(dep);
+        }
+        let order = vec!["c".to_string(), "b".to_string(), "missing".to_string()];
+
+        sort_dependencies_by_names(&mut deps, &order, &names);
+        let ids = deps.iter().map(|d| d.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![2, 1, 4, 0, 3]);
+    }
+
+    #[test]
+    fn reverse_dependents_surviving_yank_counts_both() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_survive_yank");
+        version(&conn, &foo, "1.0.0");
+        version(&conn, &foo, "2.0.0");
+        for &(name, req) in &[("bar_survive_yank", "^1.0"), ("baz_survive_yank", "^2.0"),
+                              ("qux_survive_yank", "^3.0")] {
+            let v = version(&conn, &krate(&conn, &user, name), "1.0.0");
+            let mut dep = new_dep(&v, &foo);
+            dep.req = req.into();
+            insert_dep(&conn, &dep);
+        }
+        // `qux` was never satisfied, so yanking doesn't break it. Only the
+        // latest version of a dependent counts either, and the latest one of
+        // this crate doesn't depend on `foo` at all
+        let old = krate(&conn, &user, "old_survive_yank");
+        let mut dep = new_dep(&version(&conn, &old, "1.0.0"), &foo);
+        dep.req = "^2.0".into();
+        insert_dep(&conn, &dep);
+        version(&conn, &old, "1.1.0");
+
+        let yanked = semver::Version::parse("2.0.0").unwrap();
+        assert_eq!(reverse_dependents_surviving_yank(&conn, foo.id, &yanked).unwrap(), (1, 1));
+    }
+
+    #[test]
+    fn compatibility_note_suggests_bumps() {
+        let dep = dependency("^1.2");
+        let num = |s: &str| semver::Version::parse(s).unwrap();
+        assert!(dep.satisfied_by(&num("1.4.0")));
+        assert_eq!(dep.compatibility_note(&num("1.4.0")), CompatNote::Compatible);
+        assert_eq!(dep.compatibility_note(&num("2.0.1")),
+                   CompatNote::BumpTo(semver::VersionReq::parse("^2.0.1").unwrap()));
+    }
+
+    #[test]
+    fn warns_about_dependencies_needing_newer_rust() {
+        struct Msrvs(HashMap<i32, semver::Version>);
+
+        impl MsrvSource for Msrvs {
+            fn rust_version(&self, crate_id: i32, _: &semver::Version)
+                            -> Option<semver::Version> {
+                self.0.get(&crate_id).cloned()
+            }
+        }
+
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_msrv");
+        version(&conn, &foo, "1.0.0");
+        version(&conn, &foo, "1.1.0");
+        let bar = krate(&conn, &user, "bar_msrv");
+        let v = version(&conn, &bar, "1.0.0");
+        let mut msrvs = HashMap::new();
+        msrvs.insert(foo.id, semver::Version::parse("1.20.0").unwrap());
+        msrvs.insert(bar.id, semver::Version::parse("1.15.0").unwrap());
+        let mut policy = DependencyPolicy::default();
+        policy.msrv = Some(Arc::new(Msrvs(msrvs)) as Arc<MsrvSource>);
+
+        let deps = [crate_dep("foo_msrv", "^1.0")];
+        let (_, warnings) = add_dependencies(&conn, &deps, &HashMap::new(), Resolver::V1,
+                                             v.id, &policy).unwrap();
+        assert_eq!(warnings, vec!["dependency `foo_msrv` resolves to foo_msrv 1.1.0, which \
+                                   needs Rust 1.20.0 but this crate supports Rust 1.15.0"]);
+    }
+
+    #[test]
+    fn index_size_contributors_ranks_long_feature_lists_first() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_index_size");
+        let baz = krate(&conn, &user, "baz_index_size");
+        let v = version(&conn, &krate(&conn, &user, "bar_index_size"), "1.0.0");
+        depend(&conn, &v, &foo);
+        let mut dep = new_dep(&v, &baz);
+        dep.features = vec!["serde", "std", "alloc", "derive", "unstable"];
+        let dep = insert_dep(&conn, &dep);
+
+        let sizes = index_size_contributors(&conn, v.id).unwrap();
+        assert_eq!(sizes.len(), 2);
+        assert_eq!(sizes[0], ("baz_index_size".to_string(),
+                              dep.to_index_value("baz_index_size").len()));
+        assert_eq!(sizes[1].0, "foo_index_size");
+        assert!(sizes[0].1 > sizes[1].1);
+    }
+
+    #[test]
+    fn bulk_replace_for_crate_is_atomic() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_bulk_replace");
+        let baz = krate(&conn, &user, "baz_bulk_replace");
+        let bar = krate(&conn, &user, "bar_bulk_replace");
+        let v1 = version(&conn, &bar, "1.0.0");
+        let v2 = version(&conn, &bar, "1.1.0");
+        depend(&conn, &v1, &foo);
+        depend(&conn, &v2, &foo);
+        let crate_ids = |v: &Version| {
+            find_by_version_id(&conn, v.id).unwrap().iter().map(|d| d.crate_id)
+                .collect::<Vec<_>>()
+        };
+
+        let policy = DependencyPolicy::default();
+        let mut fixes = HashMap::new();
+        fixes.insert(v1.id, vec![crate_dep("baz_bulk_replace", "^1.0")]);
+        fixes.insert(v2.id, vec![crate_dep("baz_bulk_replace", "^1.1")]);
+        bulk_replace_for_crate(&conn, bar.id, &fixes, &policy).unwrap();
+        assert_eq!(crate_ids(&v1), vec![baz.id]);
+        assert_eq!(crate_ids(&v2), vec![baz.id]);
+
+        fixes.insert(v1.id, vec![crate_dep("foo_bulk_replace", "^1.0")]);
+        fixes.insert(v2.id, vec![crate_dep("missing_bulk_replace", "^1.0")]);
+        assert!(bulk_replace_for_crate(&conn, bar.id, &fixes, &policy).is_err());
+        assert_eq!(crate_ids(&v1), vec![baz.id]);
+        assert_eq!(crate_ids(&v2), vec![baz.id]);
+    }
+
+    #[test]
+    fn replace_dependencies_applies_the_policy() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_replace_policy");
+        krate(&conn, &user, "baz_replace_policy");
+        let v = version(&conn, &krate(&conn, &user, "bar_replace_policy"), "1.0.0");
+        depend(&conn, &v, &foo);
+        let mut allowlist = HashMap::new();
+        allowlist.insert("foo_replace_policy".to_string(),
+                         semver::VersionReq::parse("^1.0").unwrap());
+        let mut policy = DependencyPolicy::default();
+        policy.allowlist = Some(Arc::new(allowlist) as Arc<DependencyAllowlist>);
+
+        let deps = [crate_dep("baz_replace_policy", "^1.0")];
+        let err = replace_dependencies(&conn, v.id, &deps, &policy).err().unwrap();
+        assert!(err.to_string().contains("allowlist"), "{}", err);
+        let remaining = find_by_version_id(&conn, v.id).unwrap();
+        assert_eq!(remaining.iter().map(|d| d.crate_id).collect::<Vec<_>>(), vec![foo.id]);
+    }
+
+    #[test]
+    fn shimmed_dependency_ids_reports_legacy_reqs() {
+        let conn = connection();
+        let user = user(&conn);
+        let foo = krate(&conn, &user, "foo_shimmed");
+        let bar = krate(&conn, &user, "bar_shimmed");
+        let v = version(&conn, &bar, "1.0.0");
+        let mut legacy = new_dep(&v, &foo);
+        legacy.req = ">=1.0 <2.0".into();
+        let legacy = insert_dep(&conn, &legacy);
+        let mut canonical = new_dep(&v, &foo);
+        canonical.req = ">= 1.0, < 2.0".into();
+        canonical.kind = Kind::Dev as i32;
+        insert_dep(&conn, &canonical);
+
+        assert_eq!(shimmed_dependency_ids(&conn, bar.id).unwrap(), vec![legacy.id]);
+        assert!(shimmed_dependency_ids(&conn, foo.id).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+//! Registry specific rules applied to dependencies when a crate is published.
+//!
+//! crates.io itself runs with the default policy, which never rejects anything
+//! on top of the checks `add_dependencies` always performs. Other registries
+//! can tighten things up through `Config::dependency_policy`.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use semver;
+
+use upload::CrateDependency;
+
+use super::{Kind, Op, req};
+use super::audit::{AuditEvent, AuditHook, Signal};
+
+#[derive(Clone, Default)]
+pub struct DependencyPolicy {
+    pub normal: KindPolicy,
+    pub build: KindPolicy,
+    pub dev: KindPolicy,
+    /// What to do with an optional dependency that no feature enables. This
+    /// only applies to the version 2 resolver, where such a dependency can
+    /// never be enabled at all.
+    pub unreferenced_optional: Severity,
+    /// The most features a version may enable across all of its dependencies
+    pub max_total_features: Option<usize>,
+    /// Store requirements as the author wrote them instead of in the form
+    /// `semver` prints them in, so the index and API show them verbatim.
+    /// They're still parsed, so invalid requirements are rejected either way.
+    pub preserve_raw_req: bool,
+    /// How many dependencies a version usually has at most. Publishing more
+    /// sends a `Signal::ManyDependencies` to the audit hook.
+    pub dependency_baseline: Option<usize>,
+    pub audit_hook: Option<Arc<AuditHook>>,
+    /// Crates which only re-export another crate. Depending on one of these
+    /// produces a warning suggesting the underlying crate instead.
+    pub facades: Option<Arc<FacadeCrates>>,
+    /// Sees every dependency before any of the other checks, and may
+    /// rewrite or reject it
+    pub interceptor: Option<Arc<DependencyInterceptor>>,
+    /// The only dependencies versions may have. Anything it doesn't cover
+    /// is rejected.
+    pub allowlist: Option<Arc<DependencyAllowlist>>,
+    /// Where to find the minimum supported Rust version of each version.
+    /// Without one, nothing is checked.
+    pub msrv: Option<Arc<MsrvSource>>,
+    /// What to do with a dependency whose newest matching version needs a
+    /// newer Rust than the version being published says it supports
+    pub msrv_mismatch: Severity,
+}
+
+/// The minimum supported Rust versions of published versions, where known
+pub trait MsrvSource: Send + Sync {
+    fn rust_version(&self, crate_id: i32, version: &semver::Version)
+                    -> Option<semver::Version>;
+}
+
+/// A curated set of crates and versions which dependencies must stay within
+pub trait DependencyAllowlist: Send + Sync {
+    /// Whether every version `req` of `krate` can match is allowed
+    fn allows(&self, krate: &str, req: &semver::VersionReq) -> bool;
+}
+
+/// A fixed range of allowed versions for each allowed crate
+impl DependencyAllowlist for HashMap<String, semver::VersionReq> {
+    fn allows(&self, krate: &str, dep_req: &semver::VersionReq) -> bool {
+        let allowed = match self.get(krate) {
+            Some(allowed) => allowed,
+            None => return false,
+        };
+        let lower_allowed = req::req_lower_bound(dep_req)
+            .map_or(false, |lower| allowed.matches(&lower));
+        let upper_allowed = match (req::req_upper_bound(dep_req),
+                                   req::req_upper_bound(allowed)) {
+            (_, None) => true,
+            (Some(upper), Some(max)) => upper <= max,
+            (None, Some(_)) => false,
+        };
+        lower_allowed && upper_allowed
+    }
+}
+
+/// A registry specific rule which can't be expressed through the rest of the
+/// policy, such as rewriting dependencies on mirrored crates
+pub trait DependencyInterceptor: Send + Sync {
+    fn inspect(&self, dep: &CrateDependency) -> InterceptAction;
+}
+
+pub enum InterceptAction {
+    /// Keep the dependency as it is
+    Accept,
+    /// Refuse the publish with this reason
+    Reject(String),
+    /// Check and insert this dependency instead
+    Transform(CrateDependency),
+}
+
+/// A source of known facade crates
+pub trait FacadeCrates: Send + Sync {
+    /// The crate `name` re-exports, if it's a facade
+    fn underlying(&self, name: &str) -> Option<String>;
+}
+
+/// A fixed mapping from facade crates to the crates they re-export
+impl FacadeCrates for HashMap<String, String> {
+    fn underlying(&self, name: &str) -> Option<String> {
+        self.get(name).cloned()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Default for Severity {
+    fn default() -> Severity {
+        Severity::Warn
+    }
+}
+
+/// The rules for dependencies of one particular kind
+#[derive(Clone, Default)]
+pub struct KindPolicy {
+    /// Reject `default-features = false` when no features are enabled in
+    /// their place, which is usually a mistake
+    pub deny_bare_no_default_features: bool,
+    /// The operators requirements may use, or `None` to allow all of them
+    pub allowed_ops: Option<HashSet<Op>>,
+}
+
+impl DependencyPolicy {
+    pub fn kind(&self, kind: Kind) -> &KindPolicy {
+        match kind {
+            Kind::Normal => &self.normal,
+            Kind::Build => &self.build,
+            Kind::Dev => &self.dev,
+        }
+    }
+
+    /// Passes `event` on to the audit hook, if one is configured
+    pub fn audit(&self, event: AuditEvent) {
+        if let Some(ref hook) = self.audit_hook {
+            hook.record(&event);
+        }
+    }
+
+    /// Passes `signal` on to the audit hook, if one is configured
+    pub fn signal(&self, signal: Signal) {
+        if let Some(ref hook) = self.audit_hook {
+            hook.signal(&signal);
+        }
+    }
+}
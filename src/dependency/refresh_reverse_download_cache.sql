@@ -0,0 +1,17 @@
+-- Only the cache is written, `crates.downloads` is the real download counter
+-- and is never touched here
+UPDATE crates SET reverse_dependency_downloads = totals.downloads
+FROM (
+    SELECT versions.crate_id, SUM(versions.downloads)::integer AS downloads
+    FROM versions
+    WHERE versions.crate_id IN (
+        SELECT versions.crate_id
+        FROM dependencies
+        INNER JOIN versions ON versions.id = dependencies.version_id
+        WHERE dependencies.crate_id = $1
+          AND NOT versions.yanked
+    )
+    GROUP BY versions.crate_id
+) totals
+WHERE crates.id = totals.crate_id
+  AND crates.reverse_dependency_downloads IS DISTINCT FROM totals.downloads
@@ -0,0 +1,233 @@
+//! Structured access to the comparators making up a `semver::VersionReq`.
+//!
+//! The version of `semver` we use doesn't expose the predicates of a
+//! `VersionReq`, so they're recovered from its canonical string form, which
+//! looks like `>= 1.0, < 2.0.0`, `^0.3`, `1.*` or `*`.
+
+use semver;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Op {
+    Exact,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+    Tilde,
+    Caret,
+    Wildcard,
+}
+
+impl Op {
+    /// The operator as it's written in a requirement
+    pub fn symbol(&self) -> &'static str {
+        match *self {
+            Op::Exact => "=",
+            Op::Greater => ">",
+            Op::GreaterEq => ">=",
+            Op::Less => "<",
+            Op::LessEq => "<=",
+            Op::Tilde => "~",
+            Op::Caret => "^",
+            Op::Wildcard => "*",
+        }
+    }
+}
+
+/// A single comparator such as `>= 1.2`. Components left out of the
+/// requirement are `None`, so `*` has no major version at all.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comparator {
+    pub op: Op,
+    pub major: Option<u64>,
+    pub minor: Option<u64>,
+    pub patch: Option<u64>,
+    pub pre: Vec<String>,
+}
+
+pub fn comparators(req: &semver::VersionReq) -> Vec<Comparator> {
+    req.to_string().split(',').map(|s| parse_comparator(s.trim())).collect()
+}
+
+fn parse_comparator(s: &str) -> Comparator {
+    // Longer operators come first so `>=` isn't mistaken for `>`
+    let ops = [(">=", Op::GreaterEq), ("<=", Op::LessEq), (">", Op::Greater),
+               ("<", Op::Less), ("=", Op::Exact), ("~", Op::Tilde),
+               ("^", Op::Caret)];
+    let (op, rest) = ops.iter()
+        .find(|&&(prefix, _)| s.starts_with(prefix))
+        .map(|&(prefix, op)| (op, s[prefix.len()..].trim()))
+        .unwrap_or((Op::Caret, s));
+
+    let (version, pre) = match rest.find('-') {
+        Some(i) => (&rest[..i], rest[i + 1..].split('.').map(String::from).collect()),
+        None => (rest, Vec::new()),
+    };
+    let mut parts = version.split('.').map(|p| p.parse::<u64>().ok());
+    let major = parts.next().and_then(|p| p);
+    let minor = parts.next().and_then(|p| p);
+    let patch = parts.next().and_then(|p| p);
+
+    let op = if version.contains('*') { Op::Wildcard } else { op };
+    Comparator { op: op, major: major, minor: minor, patch: patch, pre: pre }
+}
+
+/// Parses a requirement stored by an older version of crates.io, which
+/// accepted comparators separated by whitespace (`>= 1.0 < 2.0`) where the
+/// current parser wants commas. The returned flag is set when the
+/// requirement had to be rewritten before it would parse.
+pub fn parse_compat(s: &str) -> Result<(semver::VersionReq, bool), semver::ReqParseError> {
+    match semver::VersionReq::parse(s) {
+        Ok(req) => Ok((req, false)),
+        Err(e) => {
+            let rewritten = rewrite_legacy(s);
+            if rewritten == s {
+                return Err(e)
+            }
+            semver::VersionReq::parse(&rewritten).map(|req| (req, true))
+        }
+    }
+}
+
+fn rewrite_legacy(s: &str) -> String {
+    let mut comparators = Vec::new();
+    let mut op = String::new();
+    for token in s.split(|c: char| c == ',' || c.is_whitespace()) {
+        if token.is_empty() {
+            continue
+        }
+        // An operator on its own, as in `>= 1.0`, belongs to the next token
+        if token.chars().all(|c| "<>=~^".contains(c)) {
+            op.push_str(token);
+            continue
+        }
+        comparators.push(format!("{}{}", op, token));
+        op.clear();
+    }
+    comparators.join(", ")
+}
+
+/// How much room a requirement leaves for newer versions, from `0.0` for a
+/// pin to an exact version up to `1.0` for one without an upper bound.
+pub fn looseness(req: &semver::VersionReq) -> f64 {
+    let comparators = comparators(req);
+    let lower = comparators.iter().any(|c| c.op == Op::Greater || c.op == Op::GreaterEq);
+    let upper = comparators.iter().any(|c| c.op == Op::Less || c.op == Op::LessEq);
+    if lower && upper {
+        // Usually `>= a, < b`, which is about as loose as a caret requirement
+        return 0.75
+    }
+    comparators.iter().map(comparator_looseness).fold(1.0, f64::min)
+}
+
+fn comparator_looseness(c: &Comparator) -> f64 {
+    match (c.op, c.major, c.minor, c.patch) {
+        (Op::Greater, ..) | (Op::GreaterEq, ..) |
+        (Op::Less, ..) | (Op::LessEq, ..) => 1.0,
+        (Op::Wildcard, None, ..) => 1.0,
+        (Op::Wildcard, Some(_), None, _) => 0.75,
+        (Op::Wildcard, ..) => 0.25,
+        // `^0.0.3` and `=1.2.3` only ever match a single version
+        (Op::Caret, Some(0), Some(0), Some(_)) => 0.0,
+        (Op::Exact, _, _, Some(_)) => 0.0,
+        (Op::Exact, _, Some(_), None) | (Op::Tilde, _, Some(_), _) |
+        (Op::Caret, Some(0), Some(0), None) => 0.25,
+        (Op::Caret, Some(0), ..) => 0.5,
+        (Op::Exact, ..) | (Op::Tilde, ..) => 0.5,
+        (Op::Caret, ..) => 0.75,
+    }
+}
+
+/// The lowest version `req` can match, ignoring pre-releases, or `None` if
+/// it has no lower bound at all
+pub fn req_lower_bound(req: &semver::VersionReq) -> Option<semver::Version> {
+    comparators(req).iter().filter_map(|c| {
+        let major = match c.major {
+            Some(major) => major,
+            None => return None,
+        };
+        let (minor, patch) = (c.minor.unwrap_or(0), c.patch.unwrap_or(0));
+        match c.op {
+            Op::Less | Op::LessEq => None,
+            // `> 1.2` leaves out all of 1.2.x, `> 1.2.3` only 1.2.3 itself
+            Op::Greater => Some(match (c.minor, c.patch) {
+                (None, _) => version(major + 1, 0, 0),
+                (Some(_), None) => version(major, minor + 1, 0),
+                (Some(_), Some(_)) => version(major, minor, patch + 1),
+            }),
+            _ => Some(version(major, minor, patch)),
+        }
+    }).max()
+}
+
+/// The lowest version that's too new for `req`, or `None` if it has no upper
+/// bound. Every version `req` matches is below this one.
+pub fn req_upper_bound(req: &semver::VersionReq) -> Option<semver::Version> {
+    comparators(req).iter().filter_map(|c| {
+        let major = match c.major {
+            Some(major) => major,
+            None => return None,
+        };
+        let (minor, patch) = (c.minor.unwrap_or(0), c.patch.unwrap_or(0));
+        match (c.op, c.minor, c.patch) {
+            (Op::Greater, ..) | (Op::GreaterEq, ..) => None,
+            (Op::Less, ..) => Some(version(major, minor, patch)),
+            (Op::LessEq, None, _) | (Op::Exact, None, _) |
+            (Op::Tilde, None, _) | (Op::Wildcard, None, _) => Some(version(major + 1, 0, 0)),
+            (Op::LessEq, Some(_), None) | (Op::Exact, Some(_), None) |
+            (Op::Tilde, ..) | (Op::Wildcard, ..) => Some(version(major, minor + 1, 0)),
+            (Op::LessEq, ..) | (Op::Exact, ..) => Some(version(major, minor, patch + 1)),
+            // Caret requirements allow anything up to the first non-zero
+            // component changing
+            (Op::Caret, ..) if major > 0 || c.minor.is_none() => {
+                Some(version(major + 1, 0, 0))
+            }
+            (Op::Caret, ..) if minor > 0 || c.patch.is_none() => {
+                Some(version(0, minor + 1, 0))
+            }
+            (Op::Caret, ..) => Some(version(0, 0, patch + 1)),
+        }
+    }).min()
+}
+
+fn version(major: u64, minor: u64, patch: u64) -> semver::Version {
+    semver::Version {
+        major: major,
+        minor: minor,
+        patch: patch,
+        pre: Vec::new(),
+        build: Vec::new(),
+    }
+}
+
+/// Formats comparators the way Cargo writes requirements into the index:
+/// comparison operators are followed by a space (`>= 1, < 2`, `= 1.2.3`)
+/// while `^` and `~` aren't (`^1.0`), and only the components the author
+/// wrote are included.
+pub fn index_string(req: &semver::VersionReq) -> String {
+    comparators(req).iter().map(|c| {
+        let op = match c.op {
+            Op::Exact => "= ",
+            Op::Greater => "> ",
+            Op::GreaterEq => ">= ",
+            Op::Less => "< ",
+            Op::LessEq => "<= ",
+            Op::Tilde => "~",
+            Op::Caret => "^",
+            Op::Wildcard => "",
+        };
+        let mut parts = [c.major, c.minor, c.patch].iter()
+            .take_while(|p| p.is_some())
+            .map(|p| p.unwrap().to_string())
+            .collect::<Vec<_>>();
+        if c.op == Op::Wildcard {
+            parts.push("*".to_string());
+        }
+        let mut s = format!("{}{}", op, parts.join("."));
+        if !c.pre.is_empty() {
+            s.push('-');
+            s.push_str(&c.pre.join("."));
+        }
+        s
+    }).collect::<Vec<_>>().join(", ")
+}
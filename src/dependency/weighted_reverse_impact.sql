@@ -0,0 +1,16 @@
+-- One point for each crate with a non-yanked version depending on $1, plus
+-- one for each crate which in turn depends on that one
+SELECT COALESCE(SUM(1 + (
+    SELECT COUNT(DISTINCT versions.crate_id)
+    FROM dependencies
+    INNER JOIN versions ON versions.id = dependencies.version_id
+    WHERE dependencies.crate_id = dependents.crate_id
+      AND NOT versions.yanked
+)), 0)::bigint AS impact
+FROM (
+    SELECT DISTINCT versions.crate_id
+    FROM dependencies
+    INNER JOIN versions ON versions.id = dependencies.version_id
+    WHERE dependencies.crate_id = $1
+      AND NOT versions.yanked
+) dependents
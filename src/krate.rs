@@ -377,14 +377,22 @@ impl Crate {
 
     pub fn valid_feature_name(name: &str) -> bool {
         let mut parts = name.split('/');
-        match parts.next() {
-            Some(part) if !Crate::valid_name(part) => return false,
+        let first = match parts.next() {
+            Some(part) => part,
             None => return false,
-            _ => {}
-        }
+        };
         match parts.next() {
-            Some(part) if !Crate::valid_name(part) => return false,
-            _ => {}
+            // `pkg?/feat` enables `feat` only if `pkg` is already enabled
+            Some(part) => {
+                let pkg = if first.ends_with('?') { &first[..first.len() - 1] } else { first };
+                if !Crate::valid_name(pkg) || !Crate::valid_name(part) {
+                    return false
+                }
+            }
+            // `dep:pkg` enables the optional dependency `pkg`
+            None if first.starts_with("dep:") => return Crate::valid_name(&first[4..]),
+            None if !Crate::valid_name(first) => return false,
+            None => {}
         }
         parts.next().is_none()
     }
@@ -906,8 +914,13 @@ pub fn new(req: &mut Request) -> CargoResult<Response> {
             .save(&conn, &new_crate.authors)?;
 
         // Link this new version to all dependencies
-        let deps = dependency::add_dependencies(&conn, &new_crate.deps, version.id)?
-            .into_iter()
+        let resolver = dependency::Resolver::from_manifest(
+            new_crate.resolver.as_ref().map(|s| &**s))?;
+        let (deps, dependency_warnings) =
+            dependency::add_dependencies(&conn, &new_crate.deps, &new_crate.features,
+                                         resolver, version.id,
+                                         &app.config.dependency_policy)?;
+        let deps = deps.into_iter()
             .map(|dep| dep.git_encode(&krate.name))
             .collect();
 
@@ -952,10 +965,12 @@ pub fn new(req: &mut Request) -> CargoResult<Response> {
         struct Warnings<'a> {
             invalid_categories: Vec<&'a str>,
             invalid_badges: Vec<&'a str>,
+            dependencies: Vec<String>,
         }
         let warnings = Warnings {
             invalid_categories: ignored_invalid_categories,
             invalid_badges: ignored_invalid_badges,
+            dependencies: dependency_warnings,
         };
 
         #[derive(RustcEncodable)]
@@ -3,7 +3,7 @@ SELECT *, COUNT(*) OVER () as total FROM (
     -- Multple dependencies can exist, make it distinct
     SELECT DISTINCT ON (crate_downloads, crate_name)
     dependencies.*,
-    crates.downloads AS crate_downloads,
+    COALESCE(crates.reverse_dependency_downloads, crates.downloads) AS crate_downloads,
     crates.name AS crate_name
     FROM dependencies
     -- We only want the crates whose *max* version is dependent, so we join on a
@@ -55,6 +55,7 @@ table! {
         license -> Nullable<Varchar>,
         repository -> Nullable<Varchar>,
         max_upload_size -> Nullable<Int4>,
+        reverse_dependency_downloads -> Nullable<Int4>,
     }
 }
 
@@ -85,6 +86,7 @@ table! {
         features -> Array<Text>,
         target -> Nullable<Varchar>,
         kind -> Int4,
+        req_is_raw -> Bool,
     }
 }
 
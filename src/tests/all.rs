@@ -113,6 +113,8 @@ fn app() -> (record::Bomb, Arc<App>, conduit_middleware::MiddlewareBuilder) {
         max_upload_size: 1000,
         mirror: Replica::Primary,
         api_protocol: api_protocol,
+        dependency_policy: Default::default(),
+        dependency_workers: 1,
     };
     INIT.call_once(|| db_setup(&config.db_url));
     let app = App::new(&config);
@@ -401,6 +403,7 @@ fn new_req_body(krate: Crate, version: &str, deps: Vec<u::CrateDependency>,
         license_file: None,
         repository: krate.repository,
         badges: Some(badges),
+        resolver: None,
     }, &[])
 }
 
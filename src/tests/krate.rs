@@ -10,12 +10,13 @@ use rustc_serialize::json;
 use semver;
 
 use cargo_registry::db::RequestTransaction;
-use cargo_registry::dependency::EncodableDependency;
+use cargo_registry::dependency::{self, background, Dependency, EncodableDependency,
+                                  ReverseDependency};
 use cargo_registry::download::EncodableVersionDownload;
 use cargo_registry::keyword::{Keyword, EncodableKeyword};
 use cargo_registry::krate::{Crate, EncodableCrate};
 use cargo_registry::upload as u;
-use cargo_registry::user::EncodableUser;
+use cargo_registry::user::{User, EncodableUser};
 use cargo_registry::version::EncodableVersion;
 use cargo_registry::category::Category;
 
@@ -28,7 +29,11 @@ struct CrateMeta { total: i32 }
 #[derive(RustcDecodable)]
 struct GitCrate { name: String, vers: String, deps: Vec<String>, cksum: String }
 #[derive(RustcDecodable)]
-struct Warnings { invalid_categories: Vec<String>, invalid_badges: Vec<String> }
+struct Warnings {
+    invalid_categories: Vec<String>,
+    invalid_badges: Vec<String>,
+    dependencies: Vec<String>,
+}
 #[derive(RustcDecodable)]
 struct GoodCrate { krate: EncodableCrate, warnings: Warnings }
 #[derive(RustcDecodable)]
@@ -57,6 +62,7 @@ fn new_crate(name: &str) -> u::NewCrate {
         license_file: None,
         repository: None,
         badges: None,
+        resolver: None,
     }
 }
 
@@ -411,7 +417,7 @@ fn new_krate_with_dependency() {
         optional: false,
         default_features: true,
         features: Vec::new(),
-        version_req: u::CrateVersionReq(semver::VersionReq::parse(">= 0").unwrap()),
+        version_req: u::CrateVersionReq::parse(">= 0").unwrap(),
         target: None,
         kind: None,
     };
@@ -435,7 +441,7 @@ fn new_krate_with_wildcard_dependency() {
         optional: false,
         default_features: true,
         features: Vec::new(),
-        version_req: u::CrateVersionReq(semver::VersionReq::parse("*").unwrap()),
+        version_req: u::CrateVersionReq::parse("*").unwrap(),
         target: None,
         kind: None,
     };
@@ -560,6 +566,11 @@ fn valid_feature_names() {
     assert!(!Crate::valid_feature_name("/"));
     assert!(!Crate::valid_feature_name("%/%"));
     assert!(Crate::valid_feature_name("a/a"));
+    assert!(Crate::valid_feature_name("a?/a"));
+    assert!(!Crate::valid_feature_name("a?"));
+    assert!(!Crate::valid_feature_name("a/a?"));
+    assert!(Crate::valid_feature_name("dep:a"));
+    assert!(!Crate::valid_feature_name("dep:"));
 }
 
 #[test]
@@ -726,7 +737,7 @@ fn new_krate_dependency_missing() {
         default_features: true,
         name: u::CrateName("bar_missing".to_string()),
         features: Vec::new(),
-        version_req: u::CrateVersionReq(semver::VersionReq::parse(">= 0.0.0").unwrap()),
+        version_req: u::CrateVersionReq::parse(">= 0.0.0").unwrap(),
         target: None,
         kind: None,
     };
@@ -1296,6 +1307,262 @@ fn reverse_dependencies() {
     assert_eq!(deps.meta.total, 0);
 }
 
+#[test]
+fn reverse_dependencies_csv() {
+    let (_b, app, _middle) = ::app();
+
+    let v100 = semver::Version::parse("1.0.0").unwrap();
+    let mut req = ::req(app, Method::Get,
+                        "/api/v1/crates/c1/reverse_dependencies");
+    ::mock_user(&mut req, ::user("foo"));
+    let (c1, _) = ::mock_crate_vers(&mut req, ::krate("c1"), &v100);
+    let (_, c2v1) = ::mock_crate_vers(&mut req, ::krate("c2"), &v100);
+    ::mock_dep(&mut req, &c2v1, &c1, Some("cfg(any(unix, windows))"));
+
+    let csv = dependency::reverse_dependencies_csv(req.tx().unwrap(), c1.id).unwrap();
+    let lines = csv.lines().collect::<Vec<_>>();
+    assert_eq!(lines, vec![
+        "dependent_crate,req,kind,optional,target,downloads",
+        "c2,>= 0,normal,false,\"cfg(any(unix, windows))\",0",
+    ]);
+}
+
+#[test]
+fn weighted_reverse_impact_counts_two_levels() {
+    let (_b, app, _middle) = ::app();
+
+    let v100 = semver::Version::parse("1.0.0").unwrap();
+    let v110 = semver::Version::parse("1.1.0").unwrap();
+    let mut req = ::req(app, Method::Get, "/api/v1/crates/core/reverse_dependencies");
+    ::mock_user(&mut req, ::user("foo"));
+    let (core, _) = ::mock_crate_vers(&mut req, ::krate("core"), &v100);
+    let (a, av1) = ::mock_crate_vers(&mut req, ::krate("a"), &v100);
+    let (_, av2) = ::mock_crate_vers(&mut req, ::krate("a"), &v110);
+    let (b, bv1) = ::mock_crate_vers(&mut req, ::krate("b"), &v100);
+    let (_, cv1) = ::mock_crate_vers(&mut req, ::krate("c"), &v100);
+    let (_, dv1) = ::mock_crate_vers(&mut req, ::krate("d"), &v100);
+
+    // a and b depend on core, c and d depend on a, nothing depends on b
+    ::mock_dep(&mut req, &av1, &core, None);
+    ::mock_dep(&mut req, &av2, &core, None);
+    ::mock_dep(&mut req, &bv1, &core, None);
+    ::mock_dep(&mut req, &cv1, &a, None);
+    ::mock_dep(&mut req, &dv1, &a, None);
+
+    // (1 + 2) for a, (1 + 0) for b
+    let conn = req.tx().unwrap();
+    assert_eq!(4, dependency::weighted_reverse_impact(conn, core.id).unwrap());
+    assert_eq!(2, dependency::weighted_reverse_impact(conn, a.id).unwrap());
+    assert_eq!(0, dependency::weighted_reverse_impact(conn, b.id).unwrap());
+}
+
+#[test]
+fn refresh_reverse_download_cache_corrects_stale_counts() {
+    let (_b, app, _middle) = ::app();
+
+    let v100 = semver::Version::parse("1.0.0").unwrap();
+    let mut req = ::req(app, Method::Get, "/api/v1/crates/foo/reverse_dependencies");
+    ::mock_user(&mut req, ::user("foo"));
+    let (foo, _) = ::mock_crate_vers(&mut req, ::krate("foo"), &v100);
+    let (bar, bar_v) = ::mock_crate_vers(&mut req, ::krate("bar"), &v100);
+    let (baz, baz_v) = ::mock_crate_vers(&mut req, ::krate("baz"), &v100);
+    ::mock_dep(&mut req, &bar_v, &foo, None);
+    ::mock_dep(&mut req, &baz_v, &foo, None);
+
+    let conn = req.tx().unwrap();
+    conn.execute("UPDATE versions SET downloads = 5 WHERE id = $1", &[&bar_v.id]).unwrap();
+    conn.execute("UPDATE crates SET downloads = 100 WHERE id = $1", &[&bar.id]).unwrap();
+    conn.execute("UPDATE versions SET yanked = true WHERE id = $1", &[&baz_v.id]).unwrap();
+
+    // Only bar is still depending on foo, and its real download count is kept
+    assert_eq!(1, dependency::refresh_reverse_download_cache(conn, foo.id).unwrap());
+    let rows = conn.query("SELECT downloads, reverse_dependency_downloads FROM crates
+                           WHERE id = $1", &[&bar.id]).unwrap();
+    assert_eq!(100, rows.get(0).get::<_, i32>(0));
+    assert_eq!(Some(5), rows.get(0).get::<_, Option<i32>>(1));
+    let rows = conn.query("SELECT reverse_dependency_downloads FROM crates
+                           WHERE id = $1", &[&baz.id]).unwrap();
+    assert_eq!(None, rows.get(0).get::<_, Option<i32>>(0));
+
+    let (rev_deps, _) = foo.reverse_dependencies(conn, 0, 10).unwrap();
+    let downloads = rev_deps.into_iter().map(|d| d.encodable().downloads).collect::<Vec<_>>();
+    assert_eq!(vec![5], downloads);
+
+    assert_eq!(0, dependency::refresh_reverse_download_cache(conn, foo.id).unwrap());
+}
+
+#[test]
+fn distinct_requirements_counts_each_req() {
+    let (_b, app, _middle) = ::app();
+
+    let mut req = ::req(app, Method::Get, "/api/v1/crates/foo/reverse_dependencies");
+    ::mock_user(&mut req, ::user("foo"));
+    let (foo, _) = ::mock_crate(&mut req, ::krate("foo"));
+    for &(name, version_req) in &[("a", "^1"), ("b", "^1"), ("c", "=1.0.0")] {
+        let (_, v) = ::mock_crate(&mut req, ::krate(name));
+        let dep = ::mock_dep(&mut req, &v, &foo, None);
+        req.tx().unwrap().execute("UPDATE dependencies SET req = $1 WHERE id = $2",
+                                  &[&version_req, &dep.id]).unwrap();
+    }
+
+    let conn = req.tx().unwrap();
+    assert_eq!(dependency::distinct_requirements(conn, foo.id).unwrap(),
+               vec![("^1".to_string(), 2), ("=1.0.0".to_string(), 1)]);
+}
+
+#[test]
+fn frequent_codependencies_ranks_by_co_occurrence() {
+    let (_b, app, _middle) = ::app();
+
+    let mut req = ::req(app, Method::Get, "/api/v1/crates/a/reverse_dependencies");
+    ::mock_user(&mut req, ::user("foo"));
+    let (a, _) = ::mock_crate(&mut req, ::krate("a"));
+    let (b, _) = ::mock_crate(&mut req, ::krate("b"));
+    let (c, _) = ::mock_crate(&mut req, ::krate("c"));
+    let (d, _) = ::mock_crate(&mut req, ::krate("d"));
+    for &(name, ref others) in &[("x", vec![&b, &c]), ("y", vec![&b]), ("z", vec![&b, &d])] {
+        let (_, v) = ::mock_crate(&mut req, ::krate(name));
+        ::mock_dep(&mut req, &v, &a, None);
+        for other in others {
+            ::mock_dep(&mut req, &v, other, None);
+        }
+    }
+    // Depending on `c` without `a` doesn't count
+    let (_, w) = ::mock_crate(&mut req, ::krate("w"));
+    ::mock_dep(&mut req, &w, &c, None);
+
+    let conn = req.tx().unwrap();
+    assert_eq!(dependency::frequent_codependencies(conn, a.id, 2).unwrap(),
+               vec![("b".to_string(), 3), ("c".to_string(), 1)]);
+}
+
+#[test]
+fn dependencies_by_weight_orders_by_dependents() {
+    let (_b, app, _middle) = ::app();
+
+    let mut req = ::req(app, Method::Get, "/api/v1/crates/popular/reverse_dependencies");
+    ::mock_user(&mut req, ::user("foo"));
+    let (popular, _) = ::mock_crate(&mut req, ::krate("popular"));
+    let (niche, _) = ::mock_crate(&mut req, ::krate("niche"));
+    for name in &["a", "b"] {
+        let (_, v) = ::mock_crate(&mut req, ::krate(name));
+        ::mock_dep(&mut req, &v, &popular, None);
+    }
+    let (_, v) = ::mock_crate(&mut req, ::krate("bar"));
+    ::mock_dep(&mut req, &v, &niche, None);
+    ::mock_dep(&mut req, &v, &popular, None);
+
+    let conn = req.tx().unwrap();
+    assert_eq!(vec![("popular".to_string(), 3), ("niche".to_string(), 1)],
+               dependency::dependencies_by_weight(conn, v.id).unwrap());
+}
+
+#[test]
+fn reverse_dependencies_owned_by() {
+    let (_b, app, _middle) = ::app();
+
+    let v100 = semver::Version::parse("1.0.0").unwrap();
+    let mut req = ::req(app, Method::Get,
+                        "/api/v1/crates/c1/reverse_dependencies");
+    let foo = ::mock_user(&mut req, ::user("foo"));
+    let (c1, _) = ::mock_crate_vers(&mut req, ::krate("c1"), &v100);
+    let (_, c2v1) = ::mock_crate_vers(&mut req, ::krate("c2"), &v100);
+    ::mock_dep(&mut req, &c2v1, &c1, None);
+    ::mock_user(&mut req, ::user("bar"));
+    let (_, c3v1) = ::mock_crate_vers(&mut req, ::krate("c3"), &v100);
+    ::mock_dep(&mut req, &c3v1, &c1, None);
+
+    let rev_deps = dependency::reverse_dependencies_owned_by(req.tx().unwrap(), c1.id, foo.id)
+        .unwrap()
+        .into_iter()
+        .map(|d| d.encodable().crate_id)
+        .collect::<Vec<_>>();
+    assert_eq!(rev_deps, vec!["c2"]);
+}
+
+#[test]
+fn dependency_queries_in_background() {
+    let (_b, app, _middle) = ::app();
+    let dep = u::CrateDependency {
+        name: u::CrateName("foo_background".to_string()),
+        optional: true,
+        default_features: true,
+        features: Vec::new(),
+        version_req: u::CrateVersionReq::parse("^1.0").unwrap(),
+        target: None,
+        kind: None,
+    };
+    let (version, deps, surface) = {
+        let conn = app.diesel_database.get().unwrap();
+        let user = ::new_user("foo").create_or_update(&conn).unwrap();
+        ::new_crate("foo_background").create_or_update(&conn, None, user.id).unwrap();
+        let krate = ::new_crate("bar_background").create_or_update(&conn, None, user.id)
+            .unwrap();
+        let version = ::new_version(krate.id, "1.0.0").save(&conn, &[]).unwrap();
+        dependency::add_dependencies(&conn, &[dep], &HashMap::new(),
+                                     dependency::Resolver::V1, version.id,
+                                     &Default::default()).unwrap();
+        let deps = dependency::find_by_version_id(&conn, version.id).unwrap();
+        let surface = dependency::dependency_surface(&conn, version.id).unwrap();
+        (version, deps, surface)
+    };
+
+    // The test app only has one worker and one connection, so the second
+    // query is queued until the first has finished and handed both back
+    let first = background::find_by_version_id(&app, version.id);
+    let second = background::dependency_surface(&app, version.id);
+    assert_eq!(deps, first.wait().unwrap());
+    assert_eq!(surface, second.wait().unwrap());
+}
+
+#[test]
+fn reverse_dependencies_in_background() {
+    fn summary(rev_deps: Vec<ReverseDependency>) -> Vec<(i32, String, i32)> {
+        rev_deps.into_iter()
+            .map(|d| d.encodable())
+            .map(|d| (d.id, d.crate_id, d.downloads))
+            .collect()
+    }
+
+    let (_b, app, _middle) = ::app();
+
+    let (foo, rev_deps, total, impact) = {
+        // The test pool only has one connection, which the worker gets back
+        // once this is dropped, so it sees everything written in this
+        // transaction even though it's never committed
+        let conn = app.database.get().unwrap();
+        conn.batch_execute("BEGIN").unwrap();
+        let user = User::find_or_insert(&*conn, 1, "foo", None, None, None, "bar").unwrap();
+        let new_crate = |name: &str| {
+            Crate::find_or_insert(&*conn, name, user.id, &None, &None, &None, &None, &None,
+                                  &None, &None, None).unwrap()
+        };
+        let foo = new_crate("foo_rev_background");
+        let mut dependents = vec![new_crate("bar_rev_background"),
+                                  new_crate("baz_rev_background")];
+        let v100 = semver::Version::parse("1.0.0").unwrap();
+        for krate in &mut dependents {
+            let version = krate.add_version(&*conn, &v100, &HashMap::new(), &[]).unwrap();
+            Dependency::insert(&*conn, version.id, foo.id,
+                               &semver::VersionReq::parse("^1").unwrap(),
+                               dependency::Kind::Normal, false, true, &[], &None).unwrap();
+        }
+
+        let (rev_deps, total) = foo.reverse_dependencies(&*conn, 0, 10).unwrap();
+        let impact = dependency::weighted_reverse_impact(&*conn, foo.id).unwrap();
+        (foo, summary(rev_deps), total, impact)
+    };
+    assert_eq!(2, rev_deps.len());
+
+    let pending = background::reverse_dependencies(&app, foo.id, 0, 10);
+    let (background_rev_deps, background_total) = pending.wait().unwrap();
+    assert_eq!(rev_deps, summary(background_rev_deps));
+    assert_eq!(total, background_total);
+
+    let pending = background::weighted_reverse_impact(&app, foo.id);
+    assert_eq!(impact, pending.wait().unwrap());
+}
+
 #[test]
 fn reverse_dependencies_when_old_version_doesnt_depend_but_new_does() {
     let (_b, app, middle) = ::app();
@@ -25,19 +25,24 @@ pub struct NewCrate {
     pub license_file: Option<String>,
     pub repository: Option<String>,
     pub badges: Option<HashMap<String, HashMap<String, String>>>,
+    pub resolver: Option<String>,
 }
 
-#[derive(PartialEq, Eq, Hash)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct CrateName(pub String);
 pub struct CrateVersion(pub semver::Version);
-pub struct CrateVersionReq(pub semver::VersionReq);
+/// A parsed version requirement along with the string the author originally
+/// wrote, which may differ from the requirement's canonical form.
+#[derive(Clone)]
+pub struct CrateVersionReq(pub semver::VersionReq, pub String);
 pub struct KeywordList(pub Vec<Keyword>);
 pub struct Keyword(pub String);
 pub struct CategoryList(pub Vec<Category>);
 pub struct Category(pub String);
+#[derive(Clone)]
 pub struct Feature(pub String);
 
-#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone, RustcDecodable, RustcEncodable)]
 pub struct CrateDependency {
     pub optional: bool,
     pub default_features: bool,
@@ -102,11 +107,24 @@ impl Decodable for CrateVersion {
     }
 }
 
+impl CrateVersionReq {
+    pub fn parse(s: &str) -> Result<CrateVersionReq, semver::ReqParseError> {
+        let req = semver::VersionReq::parse(s.trim())?;
+        Ok(CrateVersionReq(req, s.to_string()))
+    }
+
+    /// Returns whether the requirement was written with leading or trailing
+    /// whitespace.
+    pub fn is_padded(&self) -> bool {
+        self.1.trim() != self.1
+    }
+}
+
 impl Decodable for CrateVersionReq {
     fn decode<D: Decoder>(d: &mut D) -> Result<CrateVersionReq, D::Error> {
         let s = d.read_str()?;
-        match semver::VersionReq::parse(&s) {
-            Ok(v) => Ok(CrateVersionReq(v)),
+        match CrateVersionReq::parse(&s) {
+            Ok(v) => Ok(v),
             Err(..) => Err(d.error(&format!("invalid version req: {}", s))),
         }
     }
@@ -191,7 +209,7 @@ impl Encodable for CrateVersion {
 
 impl Encodable for CrateVersionReq {
     fn encode<E: Encoder>(&self, d: &mut E) -> Result<(), E::Error> {
-        d.emit_str(&(**self).to_string())
+        d.emit_str(&self.1)
     }
 }
 